@@ -1,6 +1,16 @@
+// The generated code is only needed by the gRPC server, so there is nothing to build without it
+#[cfg(not(feature = "grpc"))]
+fn main() {}
+
+#[cfg(feature = "grpc")]
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    use std::{env, path::PathBuf};
+
+    let out_dir = PathBuf::from(env::var("OUT_DIR")?);
+
     tonic_prost_build::configure()
         .out_dir("src/generated")
+        .file_descriptor_set_path(out_dir.join("conmand_descriptor.bin"))
         .compile_protos(
             &[
                 "contracts/hello_world.proto",
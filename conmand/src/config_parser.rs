@@ -1,40 +1,10 @@
-use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
 
-#[derive(Debug, Clone, PartialEq)]
-pub enum ConfigValue {
-    String(String),
-    Boolean(bool),
-    Array(Vec<String>),
-}
-
-#[derive(Debug, Clone)]
-pub struct ConfigItem {
-    pub name: String,
-    pub values: HashMap<String, ConfigValue>,
-    pub directives: Vec<String>,
-}
-
-impl ConfigItem {
-    pub fn new(name: String) -> Self {
-        Self {
-            name,
-            values: HashMap::new(),
-            directives: Vec::new(),
-        }
-    }
-
-    pub fn add_value(&mut self, key: String, value: ConfigValue) {
-        self.values.insert(key, value);
-    }
+pub use crate::parser::config_item::{ConfigItem, ConfigValue};
+use crate::parser::parse_error::ParseError;
 
-    pub fn add_directive(&mut self, directive: String) {
-        self.directives.push(directive);
-    }
-}
-
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct ConfigParser;
 
 impl ConfigParser {
@@ -67,26 +37,42 @@ impl ConfigParser {
                 continue;
             }
 
-            // Look for container blocks (e.g., "legolas {")
-            if let Some(container_name) = self.extract_container_name(line) {
-                let mut config_item = ConfigItem::new(container_name);
+            // Look for container blocks (e.g., "legolas {" or "legolas { foo; }")
+            if let Some((container_name, rest)) = self.extract_container_name(line) {
+                let mut config_item = ConfigItem::with_name(container_name);
                 i += 1;
 
+                // The whole block is on one line
+                if let Some(end) = Self::find_unquoted(rest, '}') {
+                    self.parse_inline_block(&rest[..end], &mut config_item)?;
+                    items.push(config_item);
+                    continue;
+                }
+                self.parse_inline_block(rest, &mut config_item)?;
+
                 // Parse the block content
+                let mut closed = false;
                 while i < lines.len() {
                     let block_line = lines[i].trim();
 
-                    if block_line == "}" {
+                    if let Some(inner) = block_line.strip_suffix('}') {
+                        self.parse_inline_block(inner, &mut config_item)?;
                         i += 1;
+                        closed = true;
                         break;
                     }
 
                     if !block_line.is_empty() && !block_line.starts_with('#') {
-                        self.parse_config_line(block_line, &mut config_item);
+                        self.parse_config_line(block_line, &mut config_item)?;
                     }
                     i += 1;
                 }
 
+                // The file ended before the block's closing brace
+                if !closed {
+                    Err(format!("unterminated block for container {}", config_item.name))?
+                }
+
                 items.push(config_item);
             } else {
                 i += 1;
@@ -96,20 +82,42 @@ impl ConfigParser {
         Ok(items)
     }
 
-    fn extract_container_name(&self, line: &str) -> Option<String> {
-        if line.ends_with('{') {
-            let name_part = &line[..line.len() - 1].trim();
-            Some(name_part.to_string())
-        } else {
-            None
+    // The position of the first `token` that is not inside a quoted string
+    fn find_unquoted(text: &str, token: char) -> Option<usize> {
+        let mut quoted = false;
+        text.char_indices().find_map(|(index, c)| {
+            if c == '"' {
+                quoted = !quoted;
+            }
+            (c == token && !quoted).then_some(index)
+        })
+    }
+
+    fn extract_container_name<'a>(&self, line: &'a str) -> Option<(String, &'a str)> {
+        let (name_part, rest) = line.split_once('{')?;
+        Some((name_part.trim().to_string(), rest.trim()))
+    }
+
+    fn parse_inline_block(&self, content: &str, config_item: &mut ConfigItem) -> Result<(), ParseError> {
+        // Each statement keeps its ';' so it matches a line from a multi-line block.  A ';'
+        // inside a quoted value doesn't end the statement.
+        let mut rest = content;
+        while !rest.is_empty() {
+            let end = Self::find_unquoted(rest, ';').map_or(rest.len(), |index| index + 1);
+            let statement = rest[..end].trim();
+            if !statement.is_empty() && !statement.starts_with('#') {
+                self.parse_config_line(statement, config_item)?;
+            }
+            rest = &rest[end..];
         }
+        Ok(())
     }
 
-    fn parse_config_line(&self, line: &str, config_item: &mut ConfigItem) {
+    fn parse_config_line(&self, line: &str, config_item: &mut ConfigItem) -> Result<(), ParseError> {
         // Handle directives (standalone statements without =)
         if !line.contains('=') && !line.contains('+') {
             config_item.add_directive(line.to_string());
-            return;
+            return Ok(());
         }
 
         // Handle key-value pairs
@@ -122,12 +130,33 @@ impl ConfigParser {
                     .filter(|v| !v.is_empty())
                     .collect();
                 ConfigValue::Array(array_values)
+            } else if let Ok(number) = value.trim().parse::<i64>() {
+                // Integer value, only when it is not quoted
+                ConfigValue::Integer(number)
             } else {
                 // String value
                 let trimmed_value = value.trim().trim_matches('"');
                 ConfigValue::String(trimmed_value.to_string())
             };
-            config_item.add_value(key, config_value);
+
+            // '+=' extends an earlier assignment, anything else may only be assigned once
+            if line.contains("+=") && let Some(existing) = config_item.values.remove(&key) {
+                let mut values = Self::value_list(existing);
+                values.extend(Self::value_list(config_value));
+                config_item.add_value(key, ConfigValue::Array(values));
+            } else {
+                config_item.add_value_checked(key, config_value)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn value_list(value: ConfigValue) -> Vec<String> {
+        match value {
+            ConfigValue::String(value) => vec![value],
+            ConfigValue::Integer(value) => vec![value.to_string()],
+            ConfigValue::Array(values) => values,
+            ConfigValue::Boolean(_) => vec![],
         }
     }
 
@@ -155,3 +184,112 @@ impl ConfigParser {
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inline_block() -> Result<(), Box<dyn std::error::Error>> {
+        let parser = ConfigParser::new();
+        let items = parser.parse_content("legolas { ip4.addr = 1.2.3.4; mount.devfs; }")?;
+
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].name, "legolas");
+        assert_eq!(items[0].values.get("ip4.addr"), Some(&ConfigValue::String("1.2.3.4".to_string())));
+        assert_eq!(items[0].directives, vec!["mount.devfs;".to_string()]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_file() -> Result<(), Box<dyn std::error::Error>> {
+        let items = ConfigParser::new().parse_file("examples/aragorn.conf")?;
+
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].name, "aragorn");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_inline_block_quoted_brace() -> Result<(), Box<dyn std::error::Error>> {
+        let parser = ConfigParser::new();
+        let items = parser.parse_content("web { path = \"/a}b\"; mount.devfs; }\n")?;
+
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].values.get("path"), Some(&ConfigValue::String("/a}b".to_string())));
+        assert_eq!(items[0].directives, vec!["mount.devfs;".to_string()]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_inline_block_quoted_semicolon() -> Result<(), Box<dyn std::error::Error>> {
+        let parser = ConfigParser::new();
+        let items = parser.parse_content("web { exec.start = \"sh -c 'a; b'\"; persist; }\ndb {\n    exec.start = \"a; b\"; }\n")?;
+
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].values.get("exec.start"), Some(&ConfigValue::String("sh -c 'a; b'".to_string())));
+        assert_eq!(items[0].directives, vec!["persist;".to_string()]);
+        assert_eq!(items[1].values.get("exec.start"), Some(&ConfigValue::String("a; b".to_string())));
+        assert!(items[1].directives.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_multi_line_block() -> Result<(), Box<dyn std::error::Error>> {
+        let parser = ConfigParser::new();
+        let items = parser.parse_content("legolas {\n    ip4.addr = 1.2.3.4;\n}\ngimli { path = \"/jails/gimli\";\n    mount.devfs; }\n")?;
+
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].values.get("ip4.addr"), Some(&ConfigValue::String("1.2.3.4".to_string())));
+        assert_eq!(items[1].name, "gimli");
+        assert_eq!(items[1].values.get("path"), Some(&ConfigValue::String("/jails/gimli".to_string())));
+        assert_eq!(items[1].directives, vec!["mount.devfs;".to_string()]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_integer_value() -> Result<(), Box<dyn std::error::Error>> {
+        let parser = ConfigParser::new();
+        let items = parser.parse_content("legolas {\n    devfs_ruleset=5;\n    enforce_statfs = -2;\n    host=new;\n    osrelease=\"14\";\n}\n")?;
+
+        assert_eq!(items[0].values.get("devfs_ruleset"), Some(&ConfigValue::Integer(5)));
+        assert_eq!(items[0].values.get("enforce_statfs"), Some(&ConfigValue::Integer(-2)));
+        assert_eq!(items[0].values.get("host"), Some(&ConfigValue::String("new".to_string())));
+        assert_eq!(items[0].values.get("osrelease"), Some(&ConfigValue::String("14".to_string())));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_duplicate_key() {
+        let parser = ConfigParser::new();
+        let result = parser.parse_content("legolas {\n    host = new;\n    path = \"/jails/legolas\";\n    host = inherit;\n}\n");
+
+        let error = result.unwrap_err();
+        assert_eq!(error.to_string(), "duplicate directive \"host\" in block legolas");
+    }
+
+    #[test]
+    fn test_unterminated_block() {
+        let parser = ConfigParser::new();
+        let result = parser.parse_content("legolas {\n    ip4.addr = 1.2.3.4;\n}\ngimli {\n    mount.devfs;\n    path = \"/jails/gimli\";\n");
+
+        let error = result.unwrap_err();
+        assert_eq!(error.to_string(), "unterminated block for container gimli");
+    }
+
+    #[test]
+    fn test_append_value() -> Result<(), Box<dyn std::error::Error>> {
+        let parser = ConfigParser::new();
+        let items = parser.parse_content("legolas {\n    ip4.addr = 10.0.0.1;\n    ip4.addr += 10.0.0.2, 10.0.0.3;\n}\n")?;
+
+        assert_eq!(items[0].get_array("ip4.addr"), Some(&["10.0.0.1".to_string(), "10.0.0.2".to_string(), "10.0.0.3".to_string()][..]));
+
+        Ok(())
+    }
+}
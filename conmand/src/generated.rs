@@ -1,2 +1,4 @@
 pub mod container;
 pub mod hello;
+
+pub const FILE_DESCRIPTOR_SET: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/conmand_descriptor.bin"));
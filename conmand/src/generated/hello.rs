@@ -5,6 +5,13 @@ pub struct HelloRequest {
     pub name: ::prost::alloc::string::String,
 }
 #[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct HelloStreamRequest {
+    #[prost(string, tag = "1")]
+    pub name: ::prost::alloc::string::String,
+    #[prost(uint32, tag = "2")]
+    pub interval_seconds: u32,
+}
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
 pub struct HelloResponse {
     #[prost(string, tag = "1")]
     pub message: ::prost::alloc::string::String,
@@ -120,6 +127,30 @@ pub mod hello_world_client {
             req.extensions_mut().insert(GrpcMethod::new("hello.HelloWorld", "SayHello"));
             self.inner.unary(req, path, codec).await
         }
+        pub async fn say_hello_stream(
+            &mut self,
+            request: impl tonic::IntoRequest<super::HelloStreamRequest>,
+        ) -> std::result::Result<
+            tonic::Response<tonic::codec::Streaming<super::HelloResponse>>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic_prost::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/hello.HelloWorld/SayHelloStream",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("hello.HelloWorld", "SayHelloStream"));
+            self.inner.server_streaming(req, path, codec).await
+        }
     }
 }
 /// Generated server implementations.
@@ -139,6 +170,19 @@ pub mod hello_world_server {
             &self,
             request: tonic::Request<super::HelloRequest>,
         ) -> std::result::Result<tonic::Response<super::HelloResponse>, tonic::Status>;
+        /// Server streaming response type for the SayHelloStream method.
+        type SayHelloStreamStream: tonic::codegen::tokio_stream::Stream<
+                Item = std::result::Result<super::HelloResponse, tonic::Status>,
+            >
+            + std::marker::Send
+            + 'static;
+        async fn say_hello_stream(
+            &self,
+            request: tonic::Request<super::HelloStreamRequest>,
+        ) -> std::result::Result<
+            tonic::Response<Self::SayHelloStreamStream>,
+            tonic::Status,
+        >;
     }
     #[derive(Debug)]
     pub struct HelloWorldServer<T> {
@@ -259,6 +303,52 @@ pub mod hello_world_server {
                     };
                     Box::pin(fut)
                 }
+                "/hello.HelloWorld/SayHelloStream" => {
+                    #[allow(non_camel_case_types)]
+                    struct SayHelloStreamSvc<T: HelloWorld>(pub Arc<T>);
+                    impl<
+                        T: HelloWorld,
+                    > tonic::server::ServerStreamingService<super::HelloStreamRequest>
+                    for SayHelloStreamSvc<T> {
+                        type Response = super::HelloResponse;
+                        type ResponseStream = T::SayHelloStreamStream;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::ResponseStream>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::HelloStreamRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as HelloWorld>::say_hello_stream(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = SayHelloStreamSvc(inner);
+                        let codec = tonic_prost::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.server_streaming(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
                 _ => {
                     Box::pin(async move {
                         let mut response = http::Response::new(
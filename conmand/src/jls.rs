@@ -25,3 +25,4 @@
 pub mod command;
 pub mod parameters;
 pub mod configuration;
+pub mod jail_info;
@@ -22,71 +22,204 @@
 //! SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 //!
 
-use std::process::{Command, Stdio};
-use log::{warn, error};
+use std::io::{BufRead, Read};
+use std::path::PathBuf;
+use std::process::{Command, ExitStatus, Output, Stdio};
+use std::time::{Duration, Instant};
+use log::{error, warn};
 use crate::jls::configuration::Configuration;
-use crate::jls::parameters::Parameters;
+use crate::jls::parameters::{Parameters, CONFIG_DIRECTIVE, CONFIG_DIRECTIVE_RE};
 use regex::Regex;
 
-pub struct JlsCommand {}
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ListOptions {
+    pub include_dying: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct JlsCommand {
+    binary: PathBuf,
+    args: Vec<String>,
+    timeout: Duration,
+    negate_no_prefix: bool,
+}
 
-const CONFIG_DIRECTIVE_RE: &str = r#"^(?<name>[\w+\.]+)(?:=(?:(?<disabled>disable)|(?<numeric>\d+)|(?:"(?<quoted>.*)")|(?<unquoted>\w*)))?$"#;
+const DEFAULT_JLS_TIMEOUT: Duration = Duration::from_secs(5);
 
 impl JlsCommand {
     pub fn new() -> JlsCommand {
-        JlsCommand {}
+        JlsCommand {
+            binary: PathBuf::from("jls"),
+            args: vec![],
+            timeout: DEFAULT_JLS_TIMEOUT,
+            negate_no_prefix: false,
+        }
+    }
+
+    // Read a bare `nopersist` or `exec.noclean` as `persist` or `exec.clean` set to false.
+    // Off by default, since callers may look parameters up by their jls names (`nodying`).
+    pub fn with_negated_flags(mut self, negate_no_prefix: bool) -> JlsCommand {
+        self.negate_no_prefix = negate_no_prefix;
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> JlsCommand {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn with_binary<P: Into<PathBuf>>(mut self, binary: P) -> JlsCommand {
+        self.binary = binary.into();
+        self
+    }
+
+    pub fn with_args<I, S>(mut self, args: I) -> JlsCommand
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.args = args.into_iter().map(Into::into).collect();
+        self
     }
 
     pub fn list_jails(&self) -> std::result::Result<Vec<Configuration>, Box<dyn std::error::Error>> {
-        let cmd = Command::new("jls")
-            .arg("-nq")
+        self.list_jails_with(ListOptions::default())
+    }
+
+    pub fn list_jails_with(&self, opts: ListOptions) -> std::result::Result<Vec<Configuration>, Box<dyn std::error::Error>> {
+        let stdout = self.run(self.jls_args(opts))?;
+        Ok(self.parse_jls_output(stdout.as_slice())?)
+    }
+
+    // Asks jls for structured output, so values with spaces or quotes come through intact
+    #[cfg(feature = "serde")]
+    pub fn list_jails_json(&self) -> std::result::Result<Vec<Configuration>, Box<dyn std::error::Error>> {
+        let mut args = vec!["--libxo", "json", "-n"];
+        args.extend(self.args.iter().map(String::as_str));
+        let stdout = self.run(args)?;
+        parse_jls_json(&stdout)
+    }
+
+    #[tracing::instrument(level = "debug", skip_all, fields(binary = %self.binary.display(), args = ?args))]
+    fn run(&self, args: Vec<&str>) -> std::result::Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let mut cmd = Command::new(&self.binary)
+            .args(args)
             .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
             .spawn()
-            .expect("jls command failed to start");
+            .map_err(|e| format!("failed to start {}: {}", self.binary.display(), e))?;
+
+        // Read the pipes on other threads so a chatty jls can't fill them while we wait
+        let stdout = cmd.stdout.take().ok_or("jls stdout was not captured")?;
+        let stderr = cmd.stderr.take().ok_or("jls stderr was not captured")?;
+        let stdout_reader = std::thread::spawn(move || read_pipe(stdout));
+        let stderr_reader = std::thread::spawn(move || read_pipe(stderr));
+
+        let started = Instant::now();
+        let status = loop {
+            if let Some(status) = cmd.try_wait()? {
+                break status;
+            }
+            if started.elapsed() >= self.timeout {
+                cmd.kill().ok();
+                cmd.wait().ok();
+                Err(format!("{} timed out after {:?}", self.binary.display(), self.timeout))?
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        };
 
-        let output = cmd.wait_with_output()
-            .expect("jls command failed to start");
+        let read_error = |_| format!("failed to read output from {}", self.binary.display());
+        let stdout = stdout_reader.join().map_err(read_error)?.map_err(|e| e.to_string())?;
+        let stderr = stderr_reader.join().map_err(read_error)?.map_err(|e| e.to_string())?;
+        self.check_status(status, &stderr)?;
 
-        let text_representation = String::from_utf8(output.stdout)?;
-        let lines : Vec<String> = text_representation.lines().map(ToOwned::to_owned).collect();
+        Ok(stdout)
+    }
 
-        let jails = lines.iter().map(|line: &String| {
-            let parts = self.tokenize_jls_line(line).unwrap_or_default();
-            if let Ok(parameters) = self.convert_to_parameter_list(&parts) {
-                Configuration::new(parameters)
-            } else {
-                Configuration::new(vec![])
+    // The async wrappers run on tokio, which comes with the gRPC server
+    #[cfg(feature = "grpc")]
+    #[tracing::instrument(level = "debug", skip_all, fields(binary = %self.binary.display(), jails))]
+    pub async fn list_jails_async(&self) -> std::result::Result<Vec<Configuration>, Box<dyn std::error::Error + Send + Sync>> {
+        let cmd = tokio::process::Command::new(&self.binary)
+            .args(self.jls_args(ListOptions::default()))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| format!("failed to start {}: {}", self.binary.display(), e))?;
+
+        let output = tokio::time::timeout(self.timeout, cmd.wait_with_output()).await
+            .map_err(|_| format!("{} timed out after {:?}", self.binary.display(), self.timeout))?
+            .map_err(|e| format!("failed to read output from {}: {}", self.binary.display(), e))?;
+        self.check_status(output.status, &output.stderr)?;
+
+        let jails = self.parse_jls_output(output.stdout.as_slice())?;
+        tracing::Span::current().record("jails", jails.len());
+        Ok(jails)
+    }
+
+    fn check_status(&self, status: ExitStatus, stderr: &[u8]) -> std::result::Result<(), String> {
+        if status.success() {
+            Ok(())
+        } else {
+            let stderr = String::from_utf8_lossy(stderr);
+            Err(format!("{} failed ({}): {}", self.binary.display(), status, stderr.trim()))
+        }
+    }
+
+    fn jls_args(&self, opts: ListOptions) -> Vec<&str> {
+        let mut args = vec![];
+        if opts.include_dying {
+            args.push("-d");
+        }
+        args.push("-nq");
+        args.extend(self.args.iter().map(String::as_str));
+        args
+    }
+
+    // One jail per line of `jls -n` output, read from anything that yields lines, such as a
+    // captured file.  Blank lines, such as the one jls may print at the end, are skipped.
+    // Output that isn't UTF-8 is an InvalidData error.
+    pub fn parse_jls_output<R: BufRead>(&self, reader: R) -> std::result::Result<Vec<Configuration>, std::io::Error> {
+        let mut jails = vec![];
+        for line in reader.lines() {
+            let parts = self.tokenize_jls_line(line?.trim()).unwrap_or_default();
+            if parts.is_empty() {
+                continue;
             }
-        }).collect();
 
+            let (parameters, rejects) = self.try_convert_to_parameter_list(&parts);
+            for reject in rejects {
+                warn!("Skipping jls parameter {}", reject);
+            }
+            jails.push(Configuration::new(parameters));
+        }
         Ok(jails)
     }
 
     pub fn tokenize_jls_line(&self, raw: &str) -> std::result::Result<Vec<String>, Box<dyn std::error::Error>> {
         let mut result = vec![];
-        let mut in_quotes = false;
+        let mut quote: Option<char> = None;
         let mut current = String::new();
 
         for char in raw.chars() {
-            if char != ' ' {
-                current.push(char);
-            } else if char == '"' && !in_quotes {
-                in_quotes = true;
+            if quote == Some(char) {
+                quote = None;
                 current.push(char);
-            } else if in_quotes {
-                if char == '"' {
-                    in_quotes = false;
-                }
+            } else if quote.is_none() && (char == '"' || char == '\'') {
+                quote = Some(char);
                 current.push(char);
-            } else {
-                if current != "" {
+            } else if char == ' ' && quote.is_none() {
+                if !current.is_empty() {
                     result.push(current);
                 }
                 current = String::new();
+            } else {
+                current.push(char);
             }
         }
 
-        if current != "" {
+        if !current.is_empty() {
             result.push(current);
         }
 
@@ -94,36 +227,16 @@ impl JlsCommand {
     }
 
     fn directive_to_paramter(&self, expr: &Regex, directive: &str) -> std::result::Result<Parameters, Box<dyn std::error::Error>> {
-        if let Some(caps) = expr.captures(directive) {
-            let name = caps.name("name").map_or("", |m| m.as_str());
-            if name != "" {
-                if let Some(_disabled) = caps.name("disabled") {
-                    Ok(Parameters::BooleanParameter(name.to_string(), false))
-                } else if let Some(numeric) = caps.name("numeric") {
-                    let number = numeric.as_str().parse::<i32>();
-                    if let Ok(n) = number {
-                        Ok(Parameters::NumberParameter(name.to_string(), n))
-                    } else {
-                        warn!("Invalid numeric format: {} -> {}", name, numeric.as_str());
-                        Ok(Parameters::NumberParameter(name.to_string(), -1))
-                    }
-                } else if let Some(quoted) = caps.name("quoted") {
-                    Ok(Parameters::StringParameter(name.to_string(), quoted.as_str().to_string()))
-                } else if let Some(unquoted) = caps.name("unquoted") {
-                    Ok(Parameters::StringParameter(name.to_string(), unquoted.as_str().to_string()))
-                } else {
-                    Ok(Parameters::BooleanParameter(name.to_string(), true))
-                }
-            } else {
-                warn!("Attempting to parse invalid row {}", directive);
-                Ok(Parameters::StringParameter("NO NAME".to_string(), "NO VALUE".to_string()))
-            }
-        } else {
-            Err("directive does not match regex")?
+        let parameter = Parameters::from_directive(expr, directive)?;
+        if self.negate_no_prefix
+            && let Parameters::BooleanParameter(name, true) = &parameter
+            && let Some(stripped) = strip_no_prefix(name) {
+            return Ok(Parameters::BooleanParameter(stripped, false));
         }
+        Ok(parameter)
     }
 
-    pub fn convert_to_parameter_list(&self, raw : &Vec<String>) -> Result<Vec<Parameters>, Box<dyn std::error::Error>> {
+    pub fn convert_to_parameter_list(&self, raw : &[String]) -> Result<Vec<Parameters>, Box<dyn std::error::Error>> {
         let expr = Regex::new(CONFIG_DIRECTIVE_RE)?;
 
         let result : Vec<Parameters> = raw.iter().map(|val| {
@@ -139,11 +252,346 @@ impl JlsCommand {
 
         Ok(result)
     }
+
+    // Like convert_to_parameter_list, but directives that don't parse are handed back
+    // instead of being replaced with placeholder parameters.
+    pub fn try_convert_to_parameter_list(&self, raw: &[String]) -> (Vec<Parameters>, Vec<String>) {
+        let expr: &Regex = &CONFIG_DIRECTIVE;
+        let mut parameters = vec![];
+        let mut rejects = vec![];
+
+        for val in raw {
+            match self.directive_to_paramter(expr, val) {
+                Ok(parameter) => parameters.push(parameter),
+                Err(_) => rejects.push(val.clone()),
+            }
+        }
+
+        (parameters, rejects)
+    }
+}
+
+#[cfg(feature = "serde")]
+fn parse_jls_json(stdout: &[u8]) -> std::result::Result<Vec<Configuration>, Box<dyn std::error::Error>> {
+    let document: serde_json::Value = serde_json::from_slice(stdout)?;
+    let jails = document.get("jail-information")
+        .and_then(|information| information.get("jail"))
+        .and_then(serde_json::Value::as_array)
+        .ok_or("jls output has no jail-information")?;
+
+    Ok(jails.iter().map(|jail| {
+        let parameters = jail.as_object().into_iter()
+            .flatten()
+            .filter_map(|(name, value)| json_to_parameter(name, value))
+            .collect();
+        Configuration::new(parameters)
+    }).collect())
+}
+
+#[cfg(feature = "serde")]
+fn json_to_parameter(name: &str, value: &serde_json::Value) -> Option<Parameters> {
+    use serde_json::Value;
+
+    let text = |value: &Value| match value {
+        Value::String(value) => value.clone(),
+        other => other.to_string(),
+    };
+
+    match value {
+        Value::Bool(value) => Some(Parameters::BooleanParameter(name.to_string(), *value)),
+        Value::Number(number) => Some(match number.as_i64() {
+            Some(number) => Parameters::NumberParameter(name.to_string(), number),
+            None => Parameters::StringParameter(name.to_string(), number.to_string()),
+        }),
+        Value::String(value) => Some(Parameters::StringParameter(name.to_string(), value.clone())),
+        Value::Array(values) => Some(Parameters::ListParameter(name.to_string(), values.iter().map(text).collect())),
+        Value::Null | Value::Object(_) => None,
+    }
+}
+
+// `nopersist` -> `persist`, `exec.noclean` -> `exec.clean`
+fn strip_no_prefix(name: &str) -> Option<String> {
+    let (prefix, last) = match name.rsplit_once('.') {
+        Some((prefix, last)) => (format!("{}.", prefix), last),
+        None => (String::new(), name),
+    };
+    last.strip_prefix("no")
+        .filter(|stripped| !stripped.is_empty())
+        .map(|stripped| format!("{}{}", prefix, stripped))
+}
+
+fn read_pipe<R: Read>(mut pipe: R) -> std::io::Result<Vec<u8>> {
+    let mut buffer = vec![];
+    pipe.read_to_end(&mut buffer)?;
+    Ok(buffer)
+}
+
+impl Default for JlsCommand {
+    fn default() -> Self {
+        JlsCommand::new()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct JailCommand {
+    binary: PathBuf,
+}
+
+impl JailCommand {
+    pub fn new() -> JailCommand {
+        JailCommand {
+            binary: PathBuf::from("jail"),
+        }
+    }
+
+    pub fn with_binary<P: Into<PathBuf>>(mut self, binary: P) -> JailCommand {
+        self.binary = binary.into();
+        self
+    }
+
+    #[cfg(feature = "grpc")]
+    pub async fn start(&self, name: &str) -> std::result::Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        self.run("-c", name).await
+    }
+
+    #[cfg(feature = "grpc")]
+    pub async fn stop(&self, name: &str) -> std::result::Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        self.run("-r", name).await
+    }
+
+    #[cfg(feature = "grpc")]
+    async fn run(&self, flag: &str, name: &str) -> std::result::Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        let output = tokio::process::Command::new(&self.binary)
+            .arg(flag)
+            .arg(name)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .await
+            .map_err(|e| format!("failed to start {}: {}", self.binary.display(), e))?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            Err(format!("{} {} {} failed ({}): {}", self.binary.display(), flag, name, output.status, stderr.trim()))?
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+}
+
+impl Default for JailCommand {
+    fn default() -> Self {
+        JailCommand::new()
+    }
+}
+
+#[derive(Debug)]
+pub struct JexecCommand {
+    binary: PathBuf,
+}
+
+impl JexecCommand {
+    pub fn new() -> JexecCommand {
+        JexecCommand {
+            binary: PathBuf::from("jexec"),
+        }
+    }
+
+    pub fn with_binary<P: Into<PathBuf>>(mut self, binary: P) -> JexecCommand {
+        self.binary = binary.into();
+        self
+    }
+
+    pub fn exec(&self, jail: &str, argv: &[String]) -> std::result::Result<Output, Box<dyn std::error::Error>> {
+        let output = Command::new(&self.binary)
+            .arg(jail)
+            .args(argv)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .map_err(|e| format!("failed to start {}: {}", self.binary.display(), e))?;
+
+        Ok(output)
+    }
+}
+
+impl Default for JexecCommand {
+    fn default() -> Self {
+        JexecCommand::new()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::Path;
+
+    fn stub_jls(dir: &Path) -> PathBuf {
+        let stub = dir.join("jls");
+        let output = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples/test_output.txt");
+        fs::write(&stub, format!("#!/bin/sh\ncat '{}'\n", output.display())).unwrap();
+        fs::set_permissions(&stub, fs::Permissions::from_mode(0o755)).unwrap();
+        stub
+    }
+
+    #[test]
+    fn test_list_jails_with_binary() {
+        let dir = tempfile::tempdir().unwrap();
+        let jls = JlsCommand::new()
+            .with_binary(stub_jls(dir.path()))
+            .with_args(["-j", "gimli"]);
+
+        let jails = jls.list_jails().unwrap();
+        assert_eq!(jails.len(), 3);
+        assert_eq!(jails[0]["name"], Parameters::StringParameter("name".to_string(), "gimli".to_string()));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_list_jails_json() {
+        let dir = tempfile::tempdir().unwrap();
+        let stub = dir.path().join("jls");
+        let argv = dir.path().join("argv");
+        let output = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples/test_output.json");
+        fs::write(&stub, format!("#!/bin/sh\necho \"$@\" > '{}'\ncat '{}'\n", argv.display(), output.display())).unwrap();
+        fs::set_permissions(&stub, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let jails = JlsCommand::new().with_binary(&stub).list_jails_json().unwrap();
+        assert_eq!(fs::read_to_string(&argv).unwrap(), "--libxo json -n\n");
+        assert_eq!(jails.len(), 2);
+        assert_eq!(jails[0].jid(), Some(1));
+        assert_eq!(jails[0].name(), Some("gimli"));
+        assert_eq!(jails[0]["exec.start"], Parameters::StringParameter("exec.start".to_string(), "/bin/sh /etc/rc".to_string()));
+        assert_eq!(jails[0]["persist"], Parameters::BooleanParameter("persist".to_string(), false));
+        assert_eq!(jails[1]["ip4.addr"], Parameters::ListParameter(
+            "ip4.addr".to_string(),
+            vec!["192.168.0.60".to_string(), "192.168.0.70".to_string()],
+        ));
+        assert_eq!(jails[1]["securelevel"], Parameters::NumberParameter("securelevel".to_string(), -1));
+    }
+
+    #[cfg(feature = "grpc")]
+    #[tokio::test]
+    async fn test_list_jails_async() {
+        let dir = tempfile::tempdir().unwrap();
+        let jls = JlsCommand::new().with_binary(stub_jls(dir.path()));
+
+        let expected = jls.list_jails().unwrap();
+        let jails = jls.list_jails_async().await.unwrap();
+        assert_eq!(jails.len(), expected.len());
+        for (jail, expected) in jails.iter().zip(expected.iter()) {
+            assert_eq!(jail.directives, expected.directives);
+        }
+
+        let missing = JlsCommand::new().with_binary("/nonexistent/jls");
+        assert!(missing.list_jails_async().await.is_err());
+    }
+
+    #[test]
+    fn test_jexec() {
+        let dir = tempfile::tempdir().unwrap();
+        let stub = dir.path().join("jexec");
+        fs::write(&stub, "#!/bin/sh\necho \"$@\"\necho \"jail $1\" >&2\nexit 3\n").unwrap();
+        fs::set_permissions(&stub, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let jexec = JexecCommand::new().with_binary(&stub);
+        let output = jexec.exec("gimli", &["ls".to_string(), "-l".to_string(), "/tmp".to_string()]).unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "gimli ls -l /tmp\n");
+        assert_eq!(String::from_utf8_lossy(&output.stderr), "jail gimli\n");
+        assert_eq!(output.status.code(), Some(3));
+
+        let jexec = JexecCommand::new().with_binary("/nonexistent/jexec");
+        assert!(jexec.exec("gimli", &[]).is_err());
+    }
+
+    #[test]
+    fn test_list_jails_with_dying() {
+        let dir = tempfile::tempdir().unwrap();
+        let stub = dir.path().join("jls");
+        let argv = dir.path().join("argv");
+        fs::write(&stub, format!("#!/bin/sh\necho \"$@\" > '{}'\n", argv.display())).unwrap();
+        fs::set_permissions(&stub, fs::Permissions::from_mode(0o755)).unwrap();
+        let jls = JlsCommand::new().with_binary(&stub);
+
+        jls.list_jails_with(ListOptions { include_dying: true }).unwrap();
+        assert_eq!(fs::read_to_string(&argv).unwrap(), "-d -nq\n");
+
+        jls.list_jails().unwrap();
+        assert_eq!(fs::read_to_string(&argv).unwrap(), "-nq\n");
+    }
+
+    // A jls that sleeps for longer than the timeout
+    fn slow_jls(dir: &Path) -> JlsCommand {
+        let stub = dir.join("jls");
+        fs::write(&stub, "#!/bin/sh\nexec sleep 10\n").unwrap();
+        fs::set_permissions(&stub, fs::Permissions::from_mode(0o755)).unwrap();
+        JlsCommand::new()
+            .with_binary(&stub)
+            .with_timeout(Duration::from_millis(200))
+    }
+
+    // A jls that fails with a message on stderr
+    fn failing_jls(dir: &Path) -> JlsCommand {
+        let stub = dir.join("jls");
+        fs::write(&stub, "#!/bin/sh\necho 'jls: unknown parameter: foo' >&2\nexit 2\n").unwrap();
+        fs::set_permissions(&stub, fs::Permissions::from_mode(0o755)).unwrap();
+        JlsCommand::new().with_binary(&stub)
+    }
+
+    #[test]
+    fn test_list_jails_timeout() {
+        let dir = tempfile::tempdir().unwrap();
+        let jls = slow_jls(dir.path());
+
+        let started = Instant::now();
+        let result = jls.list_jails();
+        assert!(result.err().unwrap().to_string().contains("timed out"));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[cfg(feature = "grpc")]
+    #[tokio::test]
+    async fn test_list_jails_async_timeout() {
+        let dir = tempfile::tempdir().unwrap();
+        let jls = slow_jls(dir.path());
+
+        let started = Instant::now();
+        let result = jls.list_jails_async().await;
+        assert!(result.err().unwrap().to_string().contains("timed out"));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_list_jails_failure() {
+        let dir = tempfile::tempdir().unwrap();
+        let jls = failing_jls(dir.path());
+
+        let error = jls.list_jails().err().unwrap().to_string();
+        assert!(error.contains("exit status: 2"));
+        assert!(error.ends_with("jls: unknown parameter: foo"));
+    }
+
+    #[cfg(feature = "grpc")]
+    #[tokio::test]
+    async fn test_list_jails_async_failure() {
+        let dir = tempfile::tempdir().unwrap();
+        let jls = failing_jls(dir.path());
+
+        let error = jls.list_jails_async().await.err().unwrap().to_string();
+        assert!(error.contains("exit status: 2"));
+        assert!(error.ends_with("jls: unknown parameter: foo"));
+    }
+
+    #[test]
+    fn test_list_jails_missing_binary() {
+        let jls = JlsCommand::new().with_binary("/nonexistent/jls");
+
+        let result = jls.list_jails();
+        assert!(result.is_err());
+        assert!(result.err().unwrap().to_string().starts_with("failed to start /nonexistent/jls"));
+    }
 
     #[test]
     fn test_tokenize_jls_line() {
@@ -162,6 +610,17 @@ mod tests {
         assert_eq!(result, expected);
     }
 
+    #[test]
+    fn test_tokenize_quoted_spaces() {
+        let jls = JlsCommand::new();
+
+        let result = jls.tokenize_jls_line(r#"path="/var/jail one" host=new"#).unwrap();
+        assert_eq!(result, vec![r#"path="/var/jail one""#.to_string(), "host=new".to_string()]);
+
+        let parameters = jls.convert_to_parameter_list(&result).unwrap();
+        assert_eq!(parameters[0], Parameters::StringParameter("path".to_string(), "/var/jail one".to_string()));
+    }
+
     #[test]
     fn test_directive_to_paramter_boolean() {
         let expr = Regex::new(CONFIG_DIRECTIVE_RE).unwrap();
@@ -190,6 +649,36 @@ mod tests {
         let result = jls.directive_to_paramter(&expr, test_case);
         assert!(result.is_ok());
         assert_eq!(expected, result.unwrap());
+
+        let expected = Parameters::NumberParameter("securelevel".to_string(), -1);
+        let test_case = "securelevel=-1";
+        let result = jls.directive_to_paramter(&expr, test_case);
+        assert!(result.is_ok());
+        assert_eq!(expected, result.unwrap());
+    }
+
+    #[test]
+    fn test_directive_to_paramter_large_numeric() {
+        let expr = Regex::new(CONFIG_DIRECTIVE_RE).unwrap();
+        let jls = JlsCommand::new();
+
+        let expected = Parameters::NumberParameter("osreldate".to_string(), i32::MAX as i64 + 1);
+        let test_case = "osreldate=2147483648";
+        let result = jls.directive_to_paramter(&expr, test_case);
+        assert!(result.is_ok());
+        assert_eq!(expected, result.unwrap());
+
+        let expected = Parameters::NumberParameter("osreldate".to_string(), i64::MAX);
+        let test_case = "osreldate=99999999999999999999";
+        let result = jls.directive_to_paramter(&expr, test_case);
+        assert!(result.is_ok());
+        assert_eq!(expected, result.unwrap());
+
+        let expected = Parameters::NumberParameter("osreldate".to_string(), i64::MIN);
+        let test_case = "osreldate=-99999999999999999999";
+        let result = jls.directive_to_paramter(&expr, test_case);
+        assert!(result.is_ok());
+        assert_eq!(expected, result.unwrap());
     }
 
     #[test]
@@ -210,6 +699,47 @@ mod tests {
         assert_eq!(expected, result.unwrap());
     }
 
+    #[test]
+    fn test_negated_flags() {
+        let expr = Regex::new(CONFIG_DIRECTIVE_RE).unwrap();
+
+        let jls = JlsCommand::new();
+        assert_eq!(jls.directive_to_paramter(&expr, "nopersist").unwrap(), Parameters::BooleanParameter("nopersist".to_string(), true));
+
+        let jls = JlsCommand::new().with_negated_flags(true);
+        assert_eq!(jls.directive_to_paramter(&expr, "nopersist").unwrap(), Parameters::BooleanParameter("persist".to_string(), false));
+        assert_eq!(jls.directive_to_paramter(&expr, "persist").unwrap(), Parameters::BooleanParameter("persist".to_string(), true));
+        assert_eq!(jls.directive_to_paramter(&expr, "exec.noclean").unwrap(), Parameters::BooleanParameter("exec.clean".to_string(), false));
+        assert_eq!(jls.directive_to_paramter(&expr, "nodying").unwrap(), Parameters::BooleanParameter("dying".to_string(), false));
+        assert_eq!(jls.directive_to_paramter(&expr, "no").unwrap(), Parameters::BooleanParameter("no".to_string(), true));
+        assert_eq!(jls.directive_to_paramter(&expr, "ip4=disable").unwrap(), Parameters::BooleanParameter("ip4".to_string(), false));
+    }
+
+    #[test]
+    fn test_directive_to_parameter_single_quoted_string() {
+        let expr = Regex::new(CONFIG_DIRECTIVE_RE).unwrap();
+        let jls = JlsCommand::new();
+
+        let expected = Parameters::StringParameter("msg".to_string(), "hello world".to_string());
+        let test_case = "msg='hello world'";
+        let result = jls.directive_to_paramter(&expr, test_case);
+        assert!(result.is_ok());
+        assert_eq!(expected, result.unwrap());
+
+        let expected = Parameters::StringParameter("msg".to_string(), "it's here".to_string());
+        let test_case = r#"msg="it's here""#;
+        let result = jls.directive_to_paramter(&expr, test_case);
+        assert!(result.is_ok());
+        assert_eq!(expected, result.unwrap());
+
+        let tokenized = jls.tokenize_jls_line(r#"msg='hello world' note="it's here" host=new"#).unwrap();
+        assert_eq!(tokenized, vec![
+            "msg='hello world'".to_string(),
+            r#"note="it's here""#.to_string(),
+            "host=new".to_string(),
+        ]);
+    }
+
     #[test]
     fn test_directive_to_parameter_raw_string() {
         let expr = Regex::new(CONFIG_DIRECTIVE_RE).unwrap();
@@ -228,6 +758,57 @@ mod tests {
         assert_eq!(expected, result.unwrap());
     }
 
+    #[test]
+    fn test_directive_to_parameter_ip6_address() {
+        let expr = Regex::new(CONFIG_DIRECTIVE_RE).unwrap();
+        let jls = JlsCommand::new();
+
+        let expected = Parameters::StringParameter("ip6.addr".to_string(), "2001:db8::1".to_string());
+        let test_case = "ip6.addr=2001:db8::1";
+        let result = jls.directive_to_paramter(&expr, test_case);
+        assert!(result.is_ok());
+        assert_eq!(expected, result.unwrap());
+
+        let expected = Parameters::ListParameter("ip6.addr".to_string(), vec!["2001:db8::1".to_string(), "fe80::1%lo0".to_string()]);
+        let test_case = "ip6.addr=2001:db8::1,fe80::1%lo0";
+        let result = jls.directive_to_paramter(&expr, test_case);
+        assert!(result.is_ok());
+        assert_eq!(expected, result.unwrap());
+
+        let expected = Parameters::StringParameter("path".to_string(), "/usr/local/jails/containers/gimli".to_string());
+        let test_case = "path=/usr/local/jails/containers/gimli";
+        let result = jls.directive_to_paramter(&expr, test_case);
+        assert!(result.is_ok());
+        assert_eq!(expected, result.unwrap());
+    }
+
+    #[test]
+    fn test_directive_to_parameter_list() {
+        let expr = Regex::new(CONFIG_DIRECTIVE_RE).unwrap();
+        let jls = JlsCommand::new();
+
+        let expected = Parameters::ListParameter("ip4.addr".to_string(), vec!["10.0.0.1".to_string(), "10.0.0.2".to_string()]);
+        let test_case = "ip4.addr=10.0.0.1,10.0.0.2";
+        let result = jls.directive_to_paramter(&expr, test_case);
+        assert!(result.is_ok());
+        assert_eq!(expected, result.unwrap());
+
+        let expected = Parameters::ListParameter("exec.start".to_string(), vec!["a,b".to_string(), "c".to_string()]);
+        let test_case = r#"exec.start="a,b","c""#;
+        let result = jls.directive_to_paramter(&expr, test_case);
+        assert!(result.is_ok());
+        assert_eq!(expected, result.unwrap());
+
+        let expected = Parameters::StringParameter("env".to_string(), "A=1,B=2".to_string());
+        let test_case = r#"env="A=1,B=2""#;
+        let result = jls.directive_to_paramter(&expr, test_case);
+        assert!(result.is_ok());
+        assert_eq!(expected, result.unwrap());
+
+        let jail = Configuration::new(jls.convert_to_parameter_list(&["ip4.addr=10.0.0.1,10.0.0.2".to_string()]).unwrap());
+        assert!(jail.contains("ip4.addr"));
+    }
+
     #[test]
     fn test_to_parameter_list() {
         let line = "devfs_ruleset=5 nodying enforce_statfs=2 env=\"\" host=new ip4=disable";
@@ -247,4 +828,51 @@ mod tests {
         assert!(result.is_ok());
         assert_eq!(result.unwrap(), expected);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_try_convert_to_parameter_list() {
+        let jls = JlsCommand::new();
+        let tokenized = jls.tokenize_jls_line("jid=3 =oops name=legolas").unwrap();
+
+        let (parameters, rejects) = jls.try_convert_to_parameter_list(&tokenized);
+        assert_eq!(parameters, vec![
+            Parameters::NumberParameter("jid".to_string(), 3),
+            Parameters::StringParameter("name".to_string(), "legolas".to_string()),
+        ]);
+        assert_eq!(rejects, vec!["=oops".to_string()]);
+
+        let jails = jls.parse_jls_output(&b"jid=3 =oops name=legolas\n"[..]).unwrap();
+        assert_eq!(jails[0].directives.len(), 2);
+        assert!(!jails[0].contains("NO NAME"));
+    }
+
+    #[test]
+    fn test_parse_jls_output() {
+        let output = "jid=1 name=frodo path=/jails/frodo persist\n\
+                      jid=2 name=sam path=/jails/sam nopersist\n";
+        let jails = JlsCommand::new().parse_jls_output(output.as_bytes()).unwrap();
+
+        assert_eq!(jails.len(), 2);
+        assert_eq!(jails[0].jid(), Some(1));
+        assert_eq!(jails[0].name(), Some("frodo"));
+        assert_eq!(jails[0]["persist"], Parameters::BooleanParameter("persist".to_string(), true));
+        assert_eq!(jails[1].name(), Some("sam"));
+        assert_eq!(jails[1]["path"], Parameters::StringParameter("path".to_string(), "/jails/sam".to_string()));
+
+        let result = JlsCommand::new().parse_jls_output(&b"name=\xff\n"[..]);
+        assert!(result.is_err_and(|error| error.kind() == std::io::ErrorKind::InvalidData));
+    }
+
+    #[test]
+    fn test_parse_jls_output_blank_lines() {
+        let output = "jid=1 name=frodo persist \t\r\n\njid=2 name=sam\n   \n\n";
+        let jails = JlsCommand::new().parse_jls_output(output.as_bytes()).unwrap();
+
+        assert_eq!(jails.len(), 2);
+        assert_eq!(jails[0].directives.len(), 3);
+        assert_eq!(jails[1].name(), Some("sam"));
+
+        let jails = JlsCommand::new().parse_jls_output(&b"\n"[..]).unwrap();
+        assert!(jails.is_empty());
+    }
+}
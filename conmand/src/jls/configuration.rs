@@ -23,38 +23,178 @@
 //!
 
 use std::collections::HashMap;
-use crate::jls::parameters::Parameters;
-use crate::jls::command::JlsCommand;
+use crate::jls::parameters::{normalize_name, Parameters};
 
 pub struct Configuration {
+    // Parameters keyed by the name jls reported
     pub directives : HashMap<String, Parameters>,
+    // The reported name of each parameter, keyed by normalized name for lookups
+    names : HashMap<String, String>,
 }
 
+/// Parameters that differ between two configurations, each sorted by name.
+#[derive(Debug, Default, PartialEq)]
+pub struct ConfigDiff {
+    pub added: Vec<Parameters>,
+    pub removed: Vec<Parameters>,
+    pub changed: Vec<(Parameters, Parameters)>,
+}
+
+impl ConfigDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Looks up a parameter by name.
+///
+/// # Panics
+///
+/// Panics if the jail has no parameter with that name.  Use `Configuration::get` or
+/// `Configuration::get_or` when the parameter may be absent.
 impl std::ops::Index<&str> for Configuration {
     type Output = Parameters;
 
     fn index(&self, name: &str) -> &Self::Output {
-        self.directives.get(name).unwrap()
+        self.get(name).unwrap()
     }
 }
 
 impl Configuration {
     pub fn new(data : Vec<Parameters>) -> Configuration {
         let mut directives : HashMap<String, Parameters> = HashMap::new();
+        let mut names : HashMap<String, String> = HashMap::new();
         for parameter in data {
-            directives.insert(parameter.name(), parameter.clone());
+            // A later spelling of the same parameter replaces the earlier one
+            if let Some(previous) = names.insert(parameter.normalized_name(), parameter.name()) {
+                directives.remove(&previous);
+            }
+            directives.insert(parameter.name(), parameter);
         }
 
         Configuration {
             directives,
+            names,
+        }
+    }
+
+    /// Look up a parameter by name.  `_` and `.` are interchangeable, so `allow.raw_sockets`
+    /// and `allow.raw.sockets` find the same parameter.
+    pub fn get(&self, name: &str) -> Option<&Parameters> {
+        self.names.get(&normalize_name(name)).and_then(|name| self.directives.get(name))
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.names.contains_key(&normalize_name(name))
+    }
+
+    pub fn get_or<'a>(&'a self, name: &str, default: &'a Parameters) -> &'a Parameters {
+        self.get(name).unwrap_or(default)
+    }
+
+    /// Parameters sorted by name, so logs and tests see a stable order.  Each parameter is
+    /// listed under the name jls reported.
+    pub fn iter(&self) -> std::vec::IntoIter<(&str, &Parameters)> {
+        let mut parameters: Vec<(&str, &Parameters)> = self.directives.iter()
+            .map(|(name, parameter)| (name.as_str(), parameter))
+            .collect();
+        parameters.sort_by(|left, right| left.0.cmp(right.0));
+        parameters.into_iter()
+    }
+
+    /// What changed going from this configuration to the other one.
+    pub fn diff(&self, other: &Configuration) -> ConfigDiff {
+        let mut diff = ConfigDiff::default();
+
+        for (name, parameter) in self {
+            match other.get(name) {
+                None => diff.removed.push(parameter.clone()),
+                Some(new) if new != parameter => diff.changed.push((parameter.clone(), new.clone())),
+                Some(_) => {}
+            }
+        }
+
+        for (name, parameter) in other {
+            if !self.contains(name) {
+                diff.added.push(parameter.clone());
+            }
+        }
+
+        diff
+    }
+
+    pub fn jid(&self) -> Option<i64> {
+        match self.get("jid") {
+            Some(Parameters::NumberParameter(_, jid)) => Some(*jid),
+            _ => None,
+        }
+    }
+
+    /// jls reports an unset flag as `nopersist`, so anything but `persist` is not persistent.
+    pub fn is_persistent(&self) -> bool {
+        self.get("persist").and_then(Parameters::is_enabled).unwrap_or(false)
+    }
+
+    /// Only `jls -d` lists dying jails, so most output has no `dying` parameter at all.
+    pub fn is_dying(&self) -> bool {
+        self.get("dying").and_then(Parameters::is_enabled).unwrap_or(false)
+    }
+
+    /// The jail name, or its hostname if jls didn't report a name.
+    pub fn name(&self) -> Option<&str> {
+        ["name", "host.hostname"].iter().find_map(|key| match self.get(key) {
+            Some(Parameters::StringParameter(_, value)) => Some(value.as_str()),
+            _ => None,
+        })
+    }
+
+    /// Every IPv4 address and then every IPv6 address.  jls reports a single address as a
+    /// string and several as a list.
+    pub fn addresses(&self) -> Vec<String> {
+        ["ip4.addr", "ip6.addr"].iter().flat_map(|key| match self.get(key) {
+            Some(Parameters::ListParameter(_, values)) => values.clone(),
+            Some(Parameters::StringParameter(_, value)) if !value.is_empty() => vec![value.clone()],
+            _ => vec![],
+        }).collect()
+    }
+
+    /// The root directory of the jail.
+    pub fn path(&self) -> Option<&str> {
+        match self.get("path") {
+            Some(Parameters::StringParameter(_, path)) => Some(path.as_str()),
+            _ => None,
         }
     }
 }
 
+impl<'a> IntoIterator for &'a Configuration {
+    type Item = (&'a str, &'a Parameters);
+    type IntoIter = std::vec::IntoIter<(&'a str, &'a Parameters)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Serializes as an object of parameter values keyed by name.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Configuration {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.iter())
+    }
+}
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
+    use crate::jls::command::JlsCommand;
+
+    // Parse a line of jls output into a Configuration.
+    pub(crate) fn configuration(line: &str) -> Configuration {
+        let jls = JlsCommand::new();
+        let tokenized = jls.tokenize_jls_line(line).unwrap();
+        Configuration::new(jls.convert_to_parameter_list(&tokenized).unwrap())
+    }
 
     #[test]
     fn test_new_jail() {
@@ -66,4 +206,167 @@ mod tests {
         let jail = Configuration::new(parameters);
         assert_eq!(jail.directives.len(), 6);
     }
+
+    #[test]
+    fn test_reported_names() {
+        let jail = configuration("jid=3 name=web allow.raw_sockets exec.system_user=root");
+
+        let names: Vec<&str> = jail.iter().map(|(name, _)| name).collect();
+        assert_eq!(names, vec!["allow.raw_sockets", "exec.system_user", "jid", "name"]);
+        assert_eq!(jail.get("allow.raw.sockets"), jail.get("allow.raw_sockets"));
+        assert!(jail.contains("exec.system.user"));
+        assert_eq!(jail["exec.system_user"], Parameters::StringParameter("exec.system_user".to_string(), "root".to_string()));
+    }
+
+    #[test]
+    fn test_jid_and_name() {
+        let line = "devfs_ruleset=5 nodying enforce_statfs=2 env=\"\" host=new ip4=disable ip6=disable jid=3 meta=\"\" name=legolas osreldate=1500068 osrelease=15.0-RELEASE parent=0 path=/usr/local/jails/containers/legolas nopersist securelevel=-1";
+        let jail = configuration(line);
+
+        assert_eq!(jail.jid(), Some(3));
+        assert_eq!(jail.name(), Some("legolas"));
+
+        let jail = Configuration::new(vec![
+            Parameters::StringParameter("host.hostname".to_string(), "gimli.local".to_string()),
+            Parameters::StringParameter("jid".to_string(), "x".to_string()),
+        ]);
+        assert_eq!(jail.jid(), None);
+        assert_eq!(jail.name(), Some("gimli.local"));
+        assert_eq!(Configuration::new(vec![]).name(), None);
+    }
+
+    #[test]
+    fn test_persistent_and_dying() {
+        let jail = configuration("jid=3 name=legolas persist dying");
+        assert!(jail.is_persistent());
+        assert!(jail.is_dying());
+
+        let jail = configuration("jid=3 name=legolas nopersist nodying");
+        assert!(!jail.is_persistent());
+        assert!(!jail.is_dying());
+
+        let negated = JlsCommand::new().with_negated_flags(true);
+        let tokenized = negated.tokenize_jls_line("jid=3 name=legolas nopersist").unwrap();
+        let jail = Configuration::new(negated.convert_to_parameter_list(&tokenized).unwrap());
+        assert!(!jail.is_persistent());
+
+        let jail = Configuration::new(vec![Parameters::StringParameter("name".to_string(), "gimli".to_string())]);
+        assert!(!jail.is_persistent());
+        assert!(!jail.is_dying());
+    }
+
+    #[test]
+    fn test_addresses() {
+        let jail = configuration("jid=3 name=web ip4.addr=10.0.0.1,10.0.0.2 ip6.addr=2001:db8::1,2001:db8::2 vnet=new vnet.interface=epair0b,epair1b");
+
+        assert_eq!(jail.addresses(), vec!["10.0.0.1", "10.0.0.2", "2001:db8::1", "2001:db8::2"]);
+        assert_eq!(jail["vnet.interface"], Parameters::ListParameter(
+            "vnet.interface".to_string(), vec!["epair0b".to_string(), "epair1b".to_string()]
+        ));
+        assert_eq!(jail["vnet"], Parameters::StringParameter("vnet".to_string(), "new".to_string()));
+
+        let jail = configuration("jid=4 name=db ip4.addr=10.0.0.3 ip6.addr=");
+        assert_eq!(jail.addresses(), vec!["10.0.0.3"]);
+        assert!(Configuration::new(vec![]).addresses().is_empty());
+    }
+
+    #[test]
+    fn test_path() {
+        let jail = configuration("jid=3 name=web path=/zpool/jails/web persist");
+        assert_eq!(jail.path(), Some("/zpool/jails/web"));
+
+        let jail = Configuration::new(vec![Parameters::NumberParameter("path".to_string(), 1)]);
+        assert_eq!(jail.path(), None);
+        assert_eq!(Configuration::new(vec![]).path(), None);
+    }
+
+    #[test]
+    fn test_normalized_names() {
+        let jail = Configuration::new(vec![
+            Parameters::NumberParameter("devfs_ruleset".to_string(), 5),
+            Parameters::StringParameter("host.hostname".to_string(), "gimli".to_string()),
+        ]);
+
+        assert_eq!(jail["devfs.ruleset"], Parameters::NumberParameter("devfs_ruleset".to_string(), 5));
+        assert_eq!(jail["devfs_ruleset"], Parameters::NumberParameter("devfs_ruleset".to_string(), 5));
+        assert!(jail.contains("host_hostname"));
+
+        let configured = Configuration::new(vec![
+            Parameters::NumberParameter("devfs.ruleset".to_string(), 5),
+            Parameters::StringParameter("host.hostname".to_string(), "gimli".to_string()),
+        ]);
+        assert!(jail.diff(&configured).is_empty());
+    }
+
+    #[test]
+    fn test_diff() {
+        let running = Configuration::new(vec![
+            Parameters::NumberParameter("devfs_ruleset".to_string(), 5),
+            Parameters::StringParameter("host".to_string(), "new".to_string()),
+            Parameters::StringParameter("path".to_string(), "/jails/gimli".to_string()),
+        ]);
+        let configured = Configuration::new(vec![
+            Parameters::NumberParameter("devfs_ruleset".to_string(), 4),
+            Parameters::StringParameter("path".to_string(), "/jails/gimli".to_string()),
+            Parameters::BooleanParameter("persist".to_string(), true),
+        ]);
+
+        let diff = running.diff(&configured);
+        assert_eq!(diff.added, vec![Parameters::BooleanParameter("persist".to_string(), true)]);
+        assert_eq!(diff.removed, vec![Parameters::StringParameter("host".to_string(), "new".to_string())]);
+        assert_eq!(diff.changed, vec![(
+            Parameters::NumberParameter("devfs_ruleset".to_string(), 5),
+            Parameters::NumberParameter("devfs_ruleset".to_string(), 4),
+        )]);
+        assert!(!diff.is_empty());
+        assert!(running.diff(&running).is_empty());
+    }
+
+    #[test]
+    fn test_iter() {
+        let jail = Configuration::new(vec![
+            Parameters::StringParameter("path".to_string(), "/jails/gimli".to_string()),
+            Parameters::NumberParameter("jid".to_string(), 1),
+            Parameters::BooleanParameter("nodying".to_string(), true),
+        ]);
+
+        let mut names = vec![];
+        for (name, parameter) in &jail {
+            assert_eq!(name, parameter.normalized_name());
+            names.push(name);
+        }
+        assert_eq!(names, vec!["jid", "nodying", "path"]);
+        assert_eq!(jail.iter().count(), 3);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serialize() {
+        let jail = Configuration::new(vec![
+            Parameters::StringParameter("path".to_string(), "/jails/gimli".to_string()),
+            Parameters::NumberParameter("jid".to_string(), 1),
+            Parameters::BooleanParameter("ip4".to_string(), false),
+            Parameters::ListParameter("ip4.addr".to_string(), vec!["10.0.0.1".to_string(), "10.0.0.2".to_string()]),
+        ]);
+
+        let json = serde_json::to_string(&jail).unwrap();
+        assert_eq!(json, r#"{"ip4":false,"ip4.addr":["10.0.0.1","10.0.0.2"],"jid":1,"path":"/jails/gimli"}"#);
+    }
+
+    #[test]
+    fn test_get() {
+        let jail = Configuration::new(vec![
+            Parameters::StringParameter("host".to_string(), "new".to_string()),
+            Parameters::BooleanParameter("nodying".to_string(), true),
+        ]);
+
+        assert_eq!(jail.get("host"), Some(&Parameters::StringParameter("host".to_string(), "new".to_string())));
+        assert_eq!(jail.get("path"), None);
+        assert!(jail.contains("nodying"));
+        assert!(!jail.contains("path"));
+
+        let default = Parameters::StringParameter("path".to_string(), "/".to_string());
+        assert_eq!(jail.get_or("path", &default), &default);
+        assert_eq!(jail.get_or("nodying", &default), &Parameters::BooleanParameter("nodying".to_string(), true));
+    }
 }
\ No newline at end of file
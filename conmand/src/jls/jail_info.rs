@@ -0,0 +1,102 @@
+//!
+//! Copyright (c) 2026, Paul C. Hoehne
+//!
+//! Redistribution and use in source and binary forms, with or without modification, are 
+//! permitted provided that the following conditions are met:
+//!
+//!   Redistributions of source code must retain the above copyright notice, this list of 
+//!   conditions and the following disclaimer.
+//!
+//!   Redistributions in binary form must reproduce the above copyright notice, this list of 
+//!   conditions and the following disclaimer in the documentation and/or other materials 
+//!   provided with the distribution.
+//!
+//! THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY 
+//! EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF 
+//! MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL 
+//! THE COPYRIGHT OWNER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, 
+//! SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT 
+//! OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) 
+//! HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, 
+//! OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS 
+//! SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+//!
+
+use std::collections::HashMap;
+use crate::jls::configuration::Configuration;
+use crate::jls::parameters::{normalize_name, Parameters};
+
+// The parameters most callers need, pulled out of a jls Configuration.  Everything else is
+// left in `parameters`, keyed by the name jls reported.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JailInfo {
+    pub jid: i64,
+    pub name: String,
+    pub path: Option<String>,
+    pub ip4_addr: Vec<String>,
+    pub persist: bool,
+    pub parameters: HashMap<String, Parameters>,
+}
+
+const KNOWN_KEYS: [&str; 6] = ["jid", "name", "path", "ip4.addr", "persist", "nopersist"];
+
+impl JailInfo {
+    pub fn from_configuration(configuration: &Configuration) -> Result<JailInfo, Box<dyn std::error::Error>> {
+        let jid = configuration.jid().ok_or("jail has no numeric jid")?;
+        let name = configuration.name().ok_or("jail has no name")?.to_string();
+
+        let path = match configuration.get("path") {
+            Some(Parameters::StringParameter(_, value)) => Some(value.clone()),
+            _ => None,
+        };
+
+        let ip4_addr = match configuration.get("ip4.addr") {
+            Some(Parameters::ListParameter(_, values)) => values.clone(),
+            Some(Parameters::StringParameter(_, value)) if !value.is_empty() => vec![value.clone()],
+            _ => vec![],
+        };
+
+        // jls reports the flag as either `persist` or `nopersist`.
+        let persist = matches!(configuration.get("persist"), Some(Parameters::BooleanParameter(_, true)));
+
+        let parameters = configuration.iter()
+            .filter(|(name, _)| !KNOWN_KEYS.contains(&normalize_name(name).as_str()))
+            .map(|(name, parameter)| (name.to_string(), parameter.clone()))
+            .collect();
+
+        Ok(JailInfo { jid, name, path, ip4_addr, persist, parameters })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jls::configuration::tests::configuration;
+
+    #[test]
+    fn test_from_configuration() {
+        let jail = configuration("devfs_ruleset=5 nodying enforce_statfs=2 env=\"\" host=new ip4=inherit ip4.addr=10.0.0.1,10.0.0.2 ip6=disable jid=3 meta=\"\" name=legolas osreldate=1500068 osrelease=15.0-RELEASE parent=0 path=/usr/local/jails/containers/legolas persist securelevel=-1");
+        let info = JailInfo::from_configuration(&jail).unwrap();
+
+        assert_eq!(info.jid, 3);
+        assert_eq!(info.name, "legolas");
+        assert_eq!(info.path, Some("/usr/local/jails/containers/legolas".to_string()));
+        assert_eq!(info.ip4_addr, vec!["10.0.0.1".to_string(), "10.0.0.2".to_string()]);
+        assert!(info.persist);
+        assert_eq!(info.parameters["securelevel"], Parameters::NumberParameter("securelevel".to_string(), -1));
+        assert!(info.parameters.contains_key("osrelease"));
+        assert!(!info.parameters.contains_key("jid"));
+    }
+
+    #[test]
+    fn test_from_configuration_defaults() {
+        let info = JailInfo::from_configuration(&configuration("jid=4 name=gimli nopersist ip4.addr=10.0.0.3")).unwrap();
+        assert_eq!(info.path, None);
+        assert_eq!(info.ip4_addr, vec!["10.0.0.3".to_string()]);
+        assert!(!info.persist);
+        assert!(info.parameters.is_empty());
+
+        assert!(JailInfo::from_configuration(&configuration("name=gimli")).is_err());
+        assert!(JailInfo::from_configuration(&configuration("jid=4")).is_err());
+    }
+}
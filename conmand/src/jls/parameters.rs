@@ -22,11 +22,22 @@
 //! SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 //!
 
-#[derive(Debug, PartialEq, Clone)]
+use std::fmt;
+use std::str::FromStr;
+use std::sync::LazyLock;
+use log::warn;
+use regex::Regex;
+
+pub const CONFIG_DIRECTIVE_RE: &str = r#"^(?<name>[\w+\.]+)(?:=(?:(?<disabled>disable)|(?<numeric>-?\d+)|(?:"(?<quoted>.*)")|(?:'(?<squoted>.*)')|(?<unquoted>[^\s"']*)))?$"#;
+
+pub(crate) static CONFIG_DIRECTIVE: LazyLock<Regex> = LazyLock::new(|| Regex::new(CONFIG_DIRECTIVE_RE).unwrap());
+
+#[derive(Debug, Clone)]
 pub enum Parameters {
     BooleanParameter(String, bool),
     StringParameter(String, String),
-    NumberParameter(String, i32),
+    NumberParameter(String, i64),
+    ListParameter(String, Vec<String>),
 }
 
 impl Parameters {
@@ -35,6 +46,216 @@ impl Parameters {
             Parameters::BooleanParameter(name, _) => name.clone(),
             Parameters::StringParameter(name, _) => name.clone(),
             Parameters::NumberParameter(name, _) => name.clone(),
+            Parameters::ListParameter(name, _) => name.clone(),
+        }
+    }
+
+    // jls and jail.conf don't agree on '_' or '.' as a separator, e.g. devfs_ruleset and
+    // devfs.ruleset, so names are compared with every '_' as a '.'.
+    pub fn normalized_name(&self) -> String {
+        normalize_name(&self.name())
+    }
+
+    // The value alone, for display: "true"/"false", the number, the string as is, or the list
+    // joined with commas.
+    pub fn value_as_string(&self) -> String {
+        match self {
+            Parameters::BooleanParameter(_, value) => value.to_string(),
+            Parameters::StringParameter(_, value) => value.clone(),
+            Parameters::NumberParameter(_, value) => value.to_string(),
+            Parameters::ListParameter(_, values) => values.join(","),
+        }
+    }
+
+    // Whether a boolean parameter is on.  None for any other kind of parameter.
+    pub fn is_enabled(&self) -> Option<bool> {
+        match self {
+            Parameters::BooleanParameter(_, value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn from_directive(expr: &Regex, directive: &str) -> std::result::Result<Parameters, Box<dyn std::error::Error>> {
+        if let Some(caps) = expr.captures(directive) {
+            let name = caps.name("name").map_or("", |m| m.as_str());
+            if !name.is_empty() {
+                if let Some(_disabled) = caps.name("disabled") {
+                    Ok(Parameters::BooleanParameter(name.to_string(), false))
+                } else if let Some(numeric) = caps.name("numeric") {
+                    let number = numeric.as_str().parse::<i64>();
+                    if let Ok(n) = number {
+                        Ok(Parameters::NumberParameter(name.to_string(), n))
+                    } else {
+                        let clamped = if numeric.as_str().starts_with('-') { i64::MIN } else { i64::MAX };
+                        warn!("Numeric value out of range, clamping: {} -> {}", name, numeric.as_str());
+                        Ok(Parameters::NumberParameter(name.to_string(), clamped))
+                    }
+                } else if let Some((_, value)) = directive.split_once('=')
+                    && let Some(values) = split_list(value) {
+                    Ok(Parameters::ListParameter(name.to_string(), values))
+                } else if let Some(quoted) = caps.name("quoted").or_else(|| caps.name("squoted")) {
+                    Ok(Parameters::StringParameter(name.to_string(), quoted.as_str().to_string()))
+                } else if let Some(unquoted) = caps.name("unquoted") {
+                    Ok(Parameters::StringParameter(name.to_string(), unquoted.as_str().to_string()))
+                } else {
+                    Ok(Parameters::BooleanParameter(name.to_string(), true))
+                }
+            } else {
+                warn!("Attempting to parse invalid row {}", directive);
+                Ok(Parameters::StringParameter("NO NAME".to_string(), "NO VALUE".to_string()))
+            }
+        } else {
+            Err("directive does not match regex")?
+        }
+    }
+}
+
+// Parameters are equal when their values match and their names match after normalization.
+impl PartialEq for Parameters {
+    fn eq(&self, other: &Self) -> bool {
+        let values_match = match (self, other) {
+            (Parameters::BooleanParameter(_, left), Parameters::BooleanParameter(_, right)) => left == right,
+            (Parameters::StringParameter(_, left), Parameters::StringParameter(_, right)) => left == right,
+            (Parameters::NumberParameter(_, left), Parameters::NumberParameter(_, right)) => left == right,
+            (Parameters::ListParameter(_, left), Parameters::ListParameter(_, right)) => left == right,
+            _ => false,
+        };
+        values_match && self.normalized_name() == other.normalized_name()
+    }
+}
+
+pub fn normalize_name(name: &str) -> String {
+    name.replace('_', ".")
+}
+
+fn split_list(value: &str) -> Option<Vec<String>> {
+    let mut values = vec![];
+    let mut quote: Option<char> = None;
+    let mut current = String::new();
+
+    for char in value.chars() {
+        if quote == Some(char) {
+            quote = None;
+        } else if quote.is_none() && (char == '"' || char == '\'') {
+            quote = Some(char);
+        } else if char == ',' && quote.is_none() {
+            values.push(current);
+            current = String::new();
+        } else {
+            current.push(char);
+        }
+    }
+    values.push(current);
+
+    if values.len() > 1 {
+        Some(values)
+    } else {
+        None
+    }
+}
+
+impl fmt::Display for Parameters {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Parameters::BooleanParameter(name, true) => write!(f, "{}", name),
+            Parameters::BooleanParameter(name, false) => write!(f, "{}=disable", name),
+            Parameters::StringParameter(name, value) => write!(f, "{}=\"{}\"", name, value),
+            Parameters::NumberParameter(name, value) => write!(f, "{}={}", name, value),
+            Parameters::ListParameter(name, values) => {
+                let values: Vec<String> = values.iter().map(|value| format!("\"{}\"", value)).collect();
+                write!(f, "{}={}", name, values.join(","))
+            }
         }
     }
 }
+
+// Serializes just the value, as the natural JSON type for the variant.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Parameters {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Parameters::BooleanParameter(_, value) => serializer.serialize_bool(*value),
+            Parameters::StringParameter(_, value) => serializer.serialize_str(value),
+            Parameters::NumberParameter(_, value) => serializer.serialize_i64(*value),
+            Parameters::ListParameter(_, values) => serializer.collect_seq(values),
+        }
+    }
+}
+
+impl FromStr for Parameters {
+    type Err = Box<dyn std::error::Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Parameters::from_directive(&CONFIG_DIRECTIVE, s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        assert_eq!(Parameters::BooleanParameter("nodying".to_string(), true).to_string(), "nodying");
+        assert_eq!(Parameters::BooleanParameter("ip4".to_string(), false).to_string(), "ip4=disable");
+        assert_eq!(Parameters::NumberParameter("devfs_ruleset".to_string(), 5).to_string(), "devfs_ruleset=5");
+        assert_eq!(Parameters::StringParameter("env".to_string(), "".to_string()).to_string(), "env=\"\"");
+        assert_eq!(
+            Parameters::ListParameter("ip4.addr".to_string(), vec!["10.0.0.1".to_string(), "10.0.0.2".to_string()]).to_string(),
+            "ip4.addr=\"10.0.0.1\",\"10.0.0.2\""
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let parameters = vec![
+            Parameters::BooleanParameter("nodying".to_string(), true),
+            Parameters::BooleanParameter("ip4".to_string(), false),
+            Parameters::NumberParameter("securelevel".to_string(), -1),
+            Parameters::StringParameter("host".to_string(), "new".to_string()),
+            Parameters::StringParameter("env".to_string(), "".to_string()),
+            Parameters::StringParameter("osrelease".to_string(), "15".to_string()),
+            Parameters::StringParameter("exec.start".to_string(), "/bin/sh /etc/rc".to_string()),
+            Parameters::ListParameter("ip4.addr".to_string(), vec!["10.0.0.1".to_string(), "10.0.0.2".to_string()]),
+        ];
+
+        for parameter in parameters {
+            assert_eq!(Parameters::from_str(&parameter.to_string()).unwrap(), parameter);
+        }
+    }
+
+    #[test]
+    fn test_normalized_equality() {
+        let underscore = Parameters::NumberParameter("devfs_ruleset".to_string(), 5);
+        assert_eq!(underscore.normalized_name(), "devfs.ruleset");
+        assert_eq!(underscore, Parameters::NumberParameter("devfs.ruleset".to_string(), 5));
+        assert_ne!(underscore, Parameters::NumberParameter("devfs.ruleset".to_string(), 4));
+        assert_ne!(underscore, Parameters::StringParameter("devfs_ruleset".to_string(), "5".to_string()));
+    }
+
+    #[test]
+    fn test_value_as_string() {
+        assert_eq!(Parameters::BooleanParameter("persist".to_string(), true).value_as_string(), "true");
+        assert_eq!(Parameters::BooleanParameter("ip4".to_string(), false).value_as_string(), "false");
+        assert_eq!(Parameters::NumberParameter("securelevel".to_string(), -1).value_as_string(), "-1");
+        assert_eq!(Parameters::StringParameter("exec.start".to_string(), "/bin/sh /etc/rc".to_string()).value_as_string(), "/bin/sh /etc/rc");
+        assert_eq!(
+            Parameters::ListParameter("ip4.addr".to_string(), vec!["10.0.0.1".to_string(), "10.0.0.2".to_string()]).value_as_string(),
+            "10.0.0.1,10.0.0.2"
+        );
+    }
+
+    #[test]
+    fn test_is_enabled() {
+        assert_eq!(Parameters::BooleanParameter("persist".to_string(), true).is_enabled(), Some(true));
+        assert_eq!(Parameters::BooleanParameter("ip4".to_string(), false).is_enabled(), Some(false));
+        assert_eq!(Parameters::NumberParameter("securelevel".to_string(), 1).is_enabled(), None);
+        assert_eq!(Parameters::StringParameter("host".to_string(), "true".to_string()).is_enabled(), None);
+        assert_eq!(Parameters::ListParameter("ip4.addr".to_string(), vec![]).is_enabled(), None);
+    }
+
+    #[test]
+    fn test_from_str_invalid() {
+        assert!("=5".parse::<Parameters>().is_err());
+    }
+}
@@ -0,0 +1,54 @@
+//!
+//! Copyright (c) 2026, Paul C. Hoehne
+//!
+//! Redistribution and use in source and binary forms, with or without modification, are
+//! permitted provided that the following conditions are met:
+//!
+//!   Redistributions of source code must retain the above copyright notice, this list of
+//!   conditions and the following disclaimer.
+//!
+//!   Redistributions in binary form must reproduce the above copyright notice, this list of
+//!   conditions and the following disclaimer in the documentation and/or other materials
+//!   provided with the distribution.
+//!
+//! THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY
+//! EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF
+//! MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL
+//! THE COPYRIGHT OWNER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+//! SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT
+//! OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION)
+//! HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+//! OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+//! SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+//!
+
+//! The reusable parts of conmand: the jail.conf parsers, the wrappers around `jls(8)` and
+//! `jail(8)`, and the gRPC messages the daemon serves.  The gRPC messages, the async wrappers
+//! and the daemon itself need the default `grpc` feature.  Without it, only the parsers and the
+//! blocking `jls` wrapper are built, which leaves out tonic, prost and tokio:
+//!
+//! ```text
+//! cargo test --lib --no-default-features
+//! ```
+//!
+//! ```
+//! use conmand::parser::config_parser::ConfigParser;
+//!
+//! let config = ConfigParser::new().parse_content(r#"web {
+//!     persist;
+//!     path = "/jails/web";
+//! }"#).unwrap();
+//!
+//! assert_eq!(config.name, "web");
+//! assert_eq!(config.get_string("path"), Some("/jails/web"));
+//! ```
+
+pub mod config_parser;
+#[cfg(feature = "grpc")]
+pub mod generated;
+pub mod jls;
+pub mod parser;
+
+pub use parser::config::Configuration;
+pub use parser::parse_error::ParseError;
+pub use parser::parse_jail_conf;
@@ -1,16 +1,36 @@
-mod config_parser;
-mod generated;
-mod parser;
-mod jls;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::future::Future;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::os::unix::fs::FileTypeExt;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, PoisonError, RwLock};
+use std::time::Duration;
 
-use config_parser::{ConfigItem, ConfigParser, ConfigValue};
+use clap::Parser;
+use conmand::{generated, jls, parser};
 use generated::container::{
-    GetContainersRequest, GetContainersResponse, list_containers_server::ListContainersServer,
+    GetContainersRequest, GetContainersResponse, StartContainerRequest, StartContainerResponse,
+    StopContainerRequest, StopContainerResponse, CreateContainerRequest, CreateContainerResponse,
+    list_containers_server::ListContainersServer,
 };
-use generated::hello::{HelloRequest, HelloResponse, hello_world_server::HelloWorldServer};
+use generated::hello::{HelloRequest, HelloResponse, HelloStreamRequest, hello_world_server::HelloWorldServer};
+use jls::command::{JailCommand, JlsCommand};
+use jls::parameters::Parameters;
+use log::{info, warn};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use subtle::ConstantTimeEq;
+use parser::config::Configuration;
+use parser::parser_state::is_directive_name_char;
+use tokio_stream::{Stream, StreamExt};
+use tonic::service::Interceptor;
+use tracing_subscriber::EnvFilter;
+use tracing_subscriber::fmt::format::FmtSpan;
 use tonic::{Request, Response, Status, transport::Server};
 
-use crate::generated::container::Container;
+use generated::container::Container;
 
 #[derive(Debug, Default)]
 pub struct MyHelloWorld {}
@@ -27,93 +47,1390 @@ impl generated::hello::hello_world_server::HelloWorld for MyHelloWorld {
         };
         Ok(Response::new(reply))
     }
+
+    type SayHelloStreamStream = Pin<Box<dyn Stream<Item = Result<HelloResponse, Status>> + Send>>;
+
+    // A heartbeat every interval_seconds, until the client goes away and the stream is dropped
+    async fn say_hello_stream(
+        &self,
+        request: Request<HelloStreamRequest>,
+    ) -> Result<Response<Self::SayHelloStreamStream>, Status> {
+        let request = request.into_inner();
+        if request.interval_seconds == 0 {
+            return Err(Status::invalid_argument("interval_seconds must be at least 1"));
+        }
+
+        let name = request.name;
+        let mut count = 0;
+        let interval = tokio::time::interval(Duration::from_secs(request.interval_seconds.into()));
+        let replies = tokio_stream::wrappers::IntervalStream::new(interval).map(move |_| {
+            count += 1;
+            Ok(HelloResponse { message: format!("Hello, {}! ({})", name, count) })
+        });
+
+        Ok(Response::new(Box::pin(replies)))
+    }
 }
 
-#[derive(Default, Debug)]
+// Clones share the configuration cache
+#[derive(Debug, Clone)]
 pub struct ListContainers {
-    parser: ConfigParser,
+    jls: JlsCommand,
+    jail: JailCommand,
+    config_dir: PathBuf,
+    config_glob: Option<String>,
+    // Configurations keyed by container name, or None until they are next read from the files
+    cache: Arc<RwLock<Option<HashMap<String, Configuration>>>>,
 }
 
 impl ListContainers {
     pub fn new() -> Self {
         Self {
-            parser: ConfigParser::new(),
+            jls: JlsCommand::new(),
+            jail: JailCommand::new(),
+            config_dir: PathBuf::from("examples"),
+            config_glob: None,
+            cache: Arc::new(RwLock::new(None)),
+        }
+    }
+
+    pub fn with_jail(mut self, jail: JailCommand) -> Self {
+        self.jail = jail;
+        self
+    }
+
+    pub fn with_dir<P: Into<PathBuf>>(mut self, config_dir: P) -> Self {
+        self.config_dir = config_dir.into();
+        self
+    }
+
+    // Read the files matching a glob such as /etc/jail.conf.d/**/*.conf instead of the
+    // .conf files directly in the configuration directory
+    pub fn with_glob(mut self, config_glob: impl Into<String>) -> Self {
+        self.config_glob = Some(config_glob.into());
+        self
+    }
+
+    pub fn with_jls(mut self, jls: JlsCommand) -> Self {
+        self.jls = jls;
+        self
+    }
+
+    // Re-read every configuration into the cache.  The files are parsed before the cache is
+    // locked, so readers keep the previous snapshot until the new one is complete.  The cache
+    // is keyed by name, so when two files define the same container the first file in path
+    // order wins and the other definition is skipped with a warning.
+    pub fn reload(&self) {
+        let mut paths: Vec<PathBuf> = self.config_paths().collect();
+        paths.sort();
+
+        let mut configurations: HashMap<String, Configuration> = HashMap::new();
+        let mut sources: HashMap<String, PathBuf> = HashMap::new();
+        for path in paths {
+            for configuration in Self::read_configurations(&path) {
+                if let Some(source) = sources.get(&configuration.name) {
+                    warn!("Skipping container {} in {}: already defined in {}", configuration.name, path.display(), source.display());
+                    continue;
+                }
+                sources.insert(configuration.name.clone(), path.clone());
+                configurations.insert(configuration.name.clone(), configuration);
+            }
         }
+        *self.cache.write().unwrap_or_else(PoisonError::into_inner) = Some(configurations);
+    }
+
+    // Reload on a blocking thread, so parsing the files doesn't stall the async runtime
+    async fn reload_blocking(&self) -> Result<(), tokio::task::JoinError> {
+        let reloading = self.clone();
+        tokio::task::spawn_blocking(move || reloading.reload()).await
     }
 
-    fn config_item_to_container(&self, item: &ConfigItem) -> Container {
-        let name = item.name.clone();
-        let id = item.values.get("ip4.addr").and_then(|v| match v {
-            ConfigValue::String(ip) => ip.split('.').last().and_then(|s| s.parse::<i32>().ok()),
-            _ => None,
+    // Reload whenever a file under the configuration directory, or under the base directory of
+    // the configuration glob if one is set, is created, changed or removed.
+    // Events are debounced, so a burst of writes causes one reload once things have been quiet
+    // for the debounce period.  Watching stops when the returned watcher is dropped.
+    pub fn watch(&self, debounce: Duration) -> notify::Result<RecommendedWatcher> {
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+            if let Ok(event) = event
+                && (event.kind.is_create() || event.kind.is_modify() || event.kind.is_remove()) {
+                sender.send(()).ok();
+            }
+        })?;
+        let watched = self.watched_dir();
+        watcher.watch(&watched, RecursiveMode::Recursive)?;
+
+        let list_containers = self.clone();
+        tokio::spawn(async move {
+            while receiver.recv().await.is_some() {
+                while let Ok(Some(())) = tokio::time::timeout(debounce, receiver.recv()).await {}
+
+                match list_containers.reload_blocking().await {
+                    Ok(()) => info!("Reloaded configurations from {}", watched.display()),
+                    Err(error) => warn!("Unable to reload configurations: {}", error),
+                }
+            }
         });
 
-        let dataset = item
-            .values
-            .get("path")
-            .and_then(|v| match v {
-                ConfigValue::String(path) => Some(path.clone()),
-                _ => None,
-            })
-            .unwrap_or_else(|| format!("zpool/datasets/containers/{}", name));
+        Ok(watcher)
+    }
+
+    // The directory the configuration files live under: the leading components of the glob up
+    // to the first one with a wildcard, or the configuration directory when there's no glob
+    fn watched_dir(&self) -> PathBuf {
+        let Some(pattern) = &self.config_glob else {
+            return self.config_dir.clone();
+        };
+        let base: PathBuf = Path::new(pattern).components()
+            .take_while(|component| !component.as_os_str().to_string_lossy().contains(['*', '?', '[']))
+            .collect();
+        if base.as_os_str().is_empty() { PathBuf::from(".") } else { base }
+    }
+
+    // Forget the cached configurations after a change to the files, so the next read reloads
+    fn invalidate(&self) {
+        *self.cache.write().unwrap_or_else(PoisonError::into_inner) = None;
+    }
+
+    // A snapshot of the cached configurations sorted by name, or None if they need reading
+    fn snapshot(&self) -> Option<Vec<Configuration>> {
+        self.cache.read().unwrap_or_else(PoisonError::into_inner).as_ref().map(|configurations| {
+            let mut configurations: Vec<Configuration> = configurations.values().cloned().collect();
+            configurations.sort_by(|left, right| left.name.cmp(&right.name));
+            configurations
+        })
+    }
+
+    // The cached configurations sorted by name, loading them if need be
+    async fn cached_configurations(&self) -> Vec<Configuration> {
+        if let Some(configurations) = self.snapshot() {
+            return configurations;
+        }
+        if let Err(error) = self.reload_blocking().await {
+            warn!("Unable to reload configurations: {}", error);
+        }
+        self.snapshot().unwrap_or_default()
+    }
+
+    async fn cached_configuration(&self, name: &str) -> Option<Configuration> {
+        self.cached_configurations().await.into_iter().find(|configuration| configuration.name == name)
+    }
+
+    // The containers defined in one file, or none if it can't be parsed
+    fn read_configurations(path: &Path) -> Vec<Configuration> {
+        match parser::parse_jail_conf(path) {
+            Ok(configurations) => configurations,
+            Err(error) => {
+                warn!("Skipping configuration {}: {}", path.display(), error);
+                vec![]
+            }
+        }
+    }
+
+    fn config_paths(&self) -> Box<dyn Iterator<Item = PathBuf> + Send> {
+        if let Some(pattern) = &self.config_glob {
+            return match glob::glob(pattern) {
+                Ok(paths) => Box::new(paths.flatten().filter(|path| path.is_file())),
+                Err(error) => {
+                    warn!("Invalid configuration glob {}: {}", pattern, error);
+                    Box::new(std::iter::empty())
+                }
+            };
+        }
+
+        Box::new(std::fs::read_dir(&self.config_dir)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().and_then(|s| s.to_str()) == Some("conf")))
+    }
+
+    // The jid of each jail jls reports as running, keyed by both its name and hostname.  If jls
+    // can't be run, for example when not on FreeBSD, no containers are reported as running.
+    async fn running_jails(&self) -> HashMap<String, Option<i64>> {
+        let jails = match self.jls.list_jails_async().await {
+            Ok(jails) => jails,
+            Err(error) => {
+                warn!("Unable to list running jails: {}", error);
+                return HashMap::new();
+            }
+        };
+
+        let mut running = HashMap::new();
+        for jail in jails {
+            let jid = jail.jid();
+            for key in ["name", "host.hostname"] {
+                if let Some(Parameters::StringParameter(_, value)) = jail.get(key) {
+                    running.insert(value.clone(), jid);
+                }
+            }
+        }
+        running
+    }
 
-        let addresses = vec![format!("{}.local", name)];
+    fn configuration_to_container(configuration: &Configuration, running_jails: &HashMap<String, Option<i64>>) -> Container {
+        let mut container = Container::from(configuration);
 
-        // For this example, we'll assume containers are not running
-        // In a real implementation, you'd check the actual status
-        let running = false;
+        // A jail may be running under a hostname that differs from its block name
+        let hostname = configuration.get_string("host.hostname").map(|h| h.replace("${name}", &container.name));
+        let jail = running_jails.get(&container.name)
+            .or_else(|| hostname.and_then(|h| running_jails.get(&h)));
+        container.running = jail.is_some();
+        container.id = jail.copied().flatten().and_then(|jid| i32::try_from(jid).ok());
+        container
+    }
+}
 
-        Container {
-            name,
-            id,
-            dataset,
-            addresses,
-            running,
+// Check the directives sent to create_container before they are written into a .conf file.
+// Keys must be directive names, and values may not hold quotes, backslashes, line ends or
+// other control characters, so that nothing can close the value and add directives of its own.
+fn validate_directives(directives: &HashMap<String, String>) -> Result<(), Status> {
+    for (key, value) in directives {
+        if key.is_empty() || !key.chars().all(is_directive_name_char) {
+            return Err(Status::invalid_argument(format!("invalid directive name {:?}", key)));
+        }
+        if value.chars().any(|token| token == '"' || token == '\\' || token.is_control()) {
+            return Err(Status::invalid_argument(format!("invalid value for directive {}: {:?}", key, value)));
         }
     }
+    Ok(())
+}
+
+// Build a container configuration from directive values.  An empty value is a bare directive.
+fn new_configuration(name: &str, directives: &HashMap<String, String>) -> Configuration {
+    let mut keys: Vec<&String> = directives.keys().collect();
+    keys.sort();
+
+    keys.into_iter().fold(Configuration::builder().name(name), |builder, key| {
+        let value = &directives[key];
+        if value.is_empty() {
+            builder.directive(key)
+        } else {
+            builder.value(key, value)
+        }
+    }).build()
+}
+
+impl Default for ListContainers {
+    fn default() -> Self {
+        ListContainers::new()
+    }
 }
 
 #[tonic::async_trait]
 impl generated::container::list_containers_server::ListContainers for ListContainers {
+    #[tracing::instrument(level = "debug", skip_all, fields(containers))]
     async fn get_containers(
         &self,
-        _request: Request<GetContainersRequest>,
+        request: Request<GetContainersRequest>,
     ) -> Result<Response<GetContainersResponse>, Status> {
-        let mut containers = Vec::new();
-
-        // Read all .conf files in the examples directory
-        let examples_dir = std::path::Path::new("examples");
-        if let Ok(entries) = std::fs::read_dir(examples_dir) {
-            for entry in entries.flatten() {
-                let path = entry.path();
-                if path.extension().and_then(|s| s.to_str()) == Some("conf") {
-                    if let Ok(config_items) = self.parser.parse_file(&path) {
-                        for item in config_items {
-                            containers.push(self.config_item_to_container(&item));
-                        }
-                    }
-                }
-            }
+        let request = request.into_inner();
+        let names: HashSet<String> = request.names.into_iter().collect();
+
+        // The page token is the offset of the first container on the page
+        let offset = match request.page_token.as_str() {
+            "" => 0,
+            token => token.parse::<usize>()
+                .map_err(|_| Status::invalid_argument(format!("invalid page token {:?}", token)))?,
+        };
+
+        // The cache is sorted by name, so each page picks up where the last one left off
+        let matching: Vec<Configuration> = self.cached_configurations().await.into_iter()
+            .filter(|configuration| names.is_empty() || names.contains(&configuration.name))
+            .collect();
+        let end = match request.page_size {
+            0 => matching.len(),
+            page_size => matching.len().min(offset.saturating_add(page_size as usize)),
+        };
+        let next_page_token = if end < matching.len() { end.to_string() } else { String::new() };
+
+        let running_jails = self.running_jails().await;
+        let containers: Vec<Container> = matching.iter().skip(offset).take(end.saturating_sub(offset))
+            .map(|configuration| Self::configuration_to_container(configuration, &running_jails))
+            .collect();
+
+        tracing::Span::current().record("containers", containers.len());
+        let reply = GetContainersResponse { containers, next_page_token };
+        Ok(Response::new(reply))
+    }
+
+    type StreamContainersStream = Pin<Box<dyn Stream<Item = Result<Container, Status>> + Send>>;
+
+    async fn stream_containers(
+        &self,
+        request: Request<GetContainersRequest>,
+    ) -> Result<Response<Self::StreamContainersStream>, Status> {
+        let names: HashSet<String> = request.into_inner().names.into_iter().collect();
+        let running_jails = self.running_jails().await;
+
+        // Stream from the same snapshot get_containers reads, so both list the same containers
+        let containers = self.cached_configurations().await.into_iter()
+            .filter(move |configuration| names.is_empty() || names.contains(&configuration.name))
+            .map(move |configuration| Ok(Self::configuration_to_container(&configuration, &running_jails)));
+
+        Ok(Response::new(Box::pin(tokio_stream::iter(containers))))
+    }
+
+    async fn start_container(
+        &self,
+        request: Request<StartContainerRequest>,
+    ) -> Result<Response<StartContainerResponse>, Status> {
+        let name = request.into_inner().name;
+        if self.cached_configuration(&name).await.is_none() {
+            return Err(Status::not_found(format!("no container named {}", name)));
+        }
+
+        let message = self.jail.start(&name).await
+            .map_err(|e| Status::internal(e.to_string()))?;
+
+        let reply = StartContainerResponse { success: true, message };
+        Ok(Response::new(reply))
+    }
+
+    async fn stop_container(
+        &self,
+        request: Request<StopContainerRequest>,
+    ) -> Result<Response<StopContainerResponse>, Status> {
+        let name = request.into_inner().name;
+        let configuration = self.cached_configuration(&name).await
+            .ok_or_else(|| Status::not_found(format!("no container named {}", name)))?;
+
+        let running_jails = self.running_jails().await;
+        if !Self::configuration_to_container(&configuration, &running_jails).running {
+            return Err(Status::failed_precondition(format!("container {} is not running", name)));
+        }
+
+        let message = self.jail.stop(&name).await
+            .map_err(|e| Status::internal(e.to_string()))?;
+
+        let reply = StopContainerResponse { success: true, message };
+        Ok(Response::new(reply))
+    }
+
+    async fn create_container(
+        &self,
+        request: Request<CreateContainerRequest>,
+    ) -> Result<Response<CreateContainerResponse>, Status> {
+        let request = request.into_inner();
+        let name = request.name;
+        validate_directives(&request.directives)?;
+        let configuration = new_configuration(&name, &request.directives);
+        configuration.validate_name()
+            .map_err(|e| Status::invalid_argument(format!("invalid container name {:?}: {}", name, e)))?;
+        // The template block is not a container, and would never be listed
+        if configuration.is_template() {
+            return Err(Status::invalid_argument(format!("invalid container name {:?}: reserved for the template block", name)));
+        }
+        if self.cached_configuration(&name).await.is_some() {
+            return Err(Status::already_exists(format!("container {} already exists", name)));
         }
 
-        let reply = GetContainersResponse { containers };
+        let path = self.config_dir.join(format!("{}.conf", name));
+        let content = configuration.to_config_string();
+        let mut file = std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .map_err(|e| match e.kind() {
+                std::io::ErrorKind::AlreadyExists => Status::already_exists(format!("{} already exists", path.display())),
+                _ => Status::internal(format!("unable to create {}: {}", path.display(), e)),
+            })?;
+        let written = file.write_all(content.as_bytes());
+        self.invalidate();
+        written.map_err(|e| Status::internal(format!("unable to write {}: {}", path.display(), e)))?;
+
+        let reply = CreateContainerResponse { success: true, message: path.display().to_string() };
         Ok(Response::new(reply))
     }
 }
 
+const DEFAULT_LISTEN_ADDR: &str = "127.0.0.1:50051";
+
+// How long the configuration directory must be quiet before changes are reloaded
+const CONFIG_DEBOUNCE: Duration = Duration::from_millis(500);
+
+#[derive(Parser, Debug)]
+#[command(about = "Container management daemon")]
+struct Args {
+    /// Address to listen on; overrides CONMAND_LISTEN
+    #[arg(long)]
+    listen: Option<String>,
+
+    /// Directory of .conf files to serve; overrides CONMAND_CONFIG_DIR
+    #[arg(long, value_name = "DIR")]
+    config_dir: Option<PathBuf>,
+
+    /// Glob of configuration files to serve instead of the directory, such as
+    /// /etc/jail.conf.d/**/*.conf; overrides CONMAND_CONFIG_GLOB
+    #[arg(long, value_name = "PATTERN")]
+    config_glob: Option<String>,
+
+    /// Check the .conf files in a directory and exit, without starting the server
+    #[arg(long, value_name = "DIR")]
+    check: Option<PathBuf>,
+}
+
+// Parse every configuration file in the directory, writing OK or the error for each one.
+// Returns the number of files that failed to parse.
+fn check_configurations<W: Write>(dir: &Path, out: &mut W) -> std::io::Result<usize> {
+    let mut paths: Vec<PathBuf> = ListContainers::new().with_dir(dir).config_paths().collect();
+    paths.sort();
+
+    let mut failures = 0;
+    for path in paths {
+        match parser::parse_jail_conf(&path) {
+            Ok(configurations) => writeln!(out, "OK {} ({} containers)", path.display(), configurations.len())?,
+            Err(error) => {
+                failures += 1;
+                writeln!(out, "ERROR {}: {}", path.display(), error)?;
+            }
+        }
+    }
+    Ok(failures)
+}
+
+// Where the server listens: a TCP address, or a Unix domain socket given as `unix:<path>`.
+#[derive(Debug, Clone, PartialEq)]
+enum ListenAddr {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+}
+
+impl fmt::Display for ListenAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ListenAddr::Tcp(addr) => write!(f, "{}", addr),
+            ListenAddr::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+// The --listen argument wins over the CONMAND_LISTEN environment variable, and both fall back
+// to the default address.
+fn resolve_listen_addr(env: Option<&str>, arg: Option<&str>) -> Result<ListenAddr, Box<dyn std::error::Error>> {
+    let addr = arg.or(env).unwrap_or(DEFAULT_LISTEN_ADDR);
+    if let Some(path) = addr.strip_prefix("unix:") {
+        if path.is_empty() {
+            return Err(format!("invalid listen address {:?}: missing socket path", addr).into());
+        }
+        return Ok(ListenAddr::Unix(PathBuf::from(path)));
+    }
+
+    addr.parse()
+        .map(ListenAddr::Tcp)
+        .map_err(|e| format!("invalid listen address {:?}: {}", addr, e).into())
+}
+
+// Requires an `authorization: Bearer <token>` header matching the shared token.  Without a
+// token every request is accepted.  Tokens are compared in constant time, so the time taken to
+// reject a guess doesn't reveal how much of it was right.
+#[derive(Debug, Clone)]
+struct AuthInterceptor {
+    token: Option<String>,
+}
+
+impl Interceptor for AuthInterceptor {
+    fn call(&mut self, request: Request<()>) -> Result<Request<()>, Status> {
+        let Some(token) = &self.token else {
+            return Ok(request);
+        };
+
+        let provided = request.metadata().get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        match provided {
+            Some(provided) if bool::from(provided.as_bytes().ct_eq(token.as_bytes())) => Ok(request),
+            Some(_) => Err(Status::unauthenticated("invalid token")),
+            None => Err(Status::unauthenticated("missing bearer token")),
+        }
+    }
+}
+
+// Where the server reads configurations from.  The --config-dir and --config-glob arguments win
+// over the CONMAND_CONFIG_DIR and CONMAND_CONFIG_GLOB environment variables, and without either
+// the server reads the examples directory.
+fn resolve_list_containers(env_dir: Option<&str>, arg_dir: Option<&Path>, env_glob: Option<&str>, arg_glob: Option<&str>) -> ListContainers {
+    let mut list_containers = ListContainers::new();
+    if let Some(dir) = arg_dir.or(env_dir.filter(|dir| !dir.is_empty()).map(Path::new)) {
+        list_containers = list_containers.with_dir(dir);
+    }
+    if let Some(glob) = arg_glob.or(env_glob.filter(|glob| !glob.is_empty())) {
+        list_containers = list_containers.with_glob(glob);
+    }
+    list_containers
+}
+
+// Serve until the shutdown future resolves, then let in-flight requests finish.
+async fn run_server<F: Future<Output = ()>>(addr: ListenAddr, list_containers: ListContainers, token: Option<String>, shutdown: F) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let hello_world = MyHelloWorld::default();
+
+    // ListContainers can't answer anything useful without its configuration directory
+    let (health_reporter, health_service) = tonic_health::server::health_reporter();
+    health_reporter.set_serving::<HelloWorldServer<MyHelloWorld>>().await;
+    // The watcher is kept for the life of the server, since dropping it stops the watch
+    let watched = list_containers.watched_dir();
+    let _watcher = if std::fs::read_dir(&watched).is_ok() {
+        if let Err(error) = list_containers.reload_blocking().await {
+            warn!("Unable to load configurations: {}", error);
+        }
+        health_reporter.set_serving::<ListContainersServer<ListContainers>>().await;
+        list_containers.watch(CONFIG_DEBOUNCE)
+            .inspect_err(|error| warn!("Unable to watch configuration directory {}: {}", watched.display(), error))
+            .ok()
+    } else {
+        warn!("Unable to read configuration directory {}", watched.display());
+        health_reporter.set_not_serving::<ListContainersServer<ListContainers>>().await;
+        None
+    };
+
+    let reflection_service = tonic_reflection::server::Builder::configure()
+        .register_encoded_file_descriptor_set(generated::FILE_DESCRIPTOR_SET)
+        .build_v1()?;
+
+    let auth = AuthInterceptor { token };
+
+    info!("gRPC server listening on {}", addr);
+
+    let router = Server::builder()
+        .add_service(health_service)
+        .add_service(reflection_service)
+        .add_service(HelloWorldServer::with_interceptor(hello_world, auth.clone()))
+        .add_service(ListContainersServer::with_interceptor(list_containers, auth));
+    let shutdown = async {
+        shutdown.await;
+        info!("shutting down");
+    };
+
+    match addr {
+        ListenAddr::Tcp(addr) => router.serve_with_shutdown(addr, shutdown).await?,
+        ListenAddr::Unix(path) => {
+            // A socket left behind by a previous run would make the bind fail
+            if std::fs::symlink_metadata(&path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+                std::fs::remove_file(&path)?;
+            }
+            let listener = tokio::net::UnixListener::bind(&path)?;
+            let incoming = tokio_stream::wrappers::UnixListenerStream::new(listener);
+            router.serve_with_incoming_shutdown(incoming, shutdown).await?
+        }
+    }
+
+    Ok(())
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let addr = "127.0.0.1:50051".parse()?;
-    let hello_world = MyHelloWorld::default();
-    let list_containers = ListContainers::new();
+    // RUST_LOG=debug adds the parse and jls spans, with their timings when they close
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")))
+        .with_span_events(FmtSpan::CLOSE)
+        .init();
+
+    let args = Args::parse();
+    if let Some(dir) = args.check {
+        let failures = check_configurations(&dir, &mut std::io::stdout())?;
+        if failures > 0 {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
 
-    println!("gRPC server listening on {}", addr);
+    let env = std::env::var("CONMAND_LISTEN").ok();
+    let addr = resolve_listen_addr(env.as_deref(), args.listen.as_deref())?;
+    let token = std::env::var("CONMAND_TOKEN").ok().filter(|token| !token.is_empty());
+    let list_containers = resolve_list_containers(
+        std::env::var("CONMAND_CONFIG_DIR").ok().as_deref(),
+        args.config_dir.as_deref(),
+        std::env::var("CONMAND_CONFIG_GLOB").ok().as_deref(),
+        args.config_glob.as_deref(),
+    );
 
-    Server::builder()
-        .add_service(HelloWorldServer::new(hello_world))
-        .add_service(ListContainersServer::new(list_containers))
-        .serve(addr)
-        .await?;
+    run_server(addr, list_containers, token, async {
+        if let Err(error) = tokio::signal::ctrl_c().await {
+            warn!("Unable to listen for shutdown signal: {}", error);
+        }
+    }).await.map_err(|e| e as Box<dyn std::error::Error>)?;
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::{Path, PathBuf};
+
+    // Keeps every warning logged by the tests, so a test can check what it logged
+    struct CapturingLogger;
+
+    static WARNINGS: std::sync::Mutex<Vec<String>> = std::sync::Mutex::new(Vec::new());
+    static LOGGER: CapturingLogger = CapturingLogger;
+
+    impl log::Log for CapturingLogger {
+        fn enabled(&self, metadata: &log::Metadata) -> bool {
+            metadata.level() <= log::Level::Warn
+        }
+
+        fn log(&self, record: &log::Record) {
+            if self.enabled(record.metadata()) {
+                WARNINGS.lock().unwrap().push(record.args().to_string());
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    fn capture_warnings() {
+        if log::set_logger(&LOGGER).is_ok() {
+            log::set_max_level(log::LevelFilter::Warn);
+        }
+    }
+
+    // Keeps the name and path field of every span opened while it is the subscriber
+    #[derive(Clone, Default)]
+    struct SpanRecorder(std::sync::Arc<std::sync::Mutex<Vec<(String, String)>>>);
+
+    impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for SpanRecorder {
+        fn on_new_span(&self, attrs: &tracing::span::Attributes<'_>, _id: &tracing::span::Id,
+                       _ctx: tracing_subscriber::layer::Context<'_, S>) {
+            let mut path = PathField::default();
+            attrs.record(&mut path);
+            self.0.lock().unwrap().push((attrs.metadata().name().to_string(), path.0));
+        }
+    }
+
+    #[derive(Default)]
+    struct PathField(String);
+
+    impl tracing::field::Visit for PathField {
+        fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn fmt::Debug) {
+            if field.name() == "path" {
+                self.0 = format!("{:?}", value);
+            }
+        }
+    }
+
+    fn stub_command(dir: &Path, name: &str, script: &str) -> PathBuf {
+        let stub = dir.join(name);
+        fs::write(&stub, format!("#!/bin/sh\n{}", script)).unwrap();
+        fs::set_permissions(&stub, fs::Permissions::from_mode(0o755)).unwrap();
+        stub
+    }
+
+    // Write a `<name>.conf` holding a persistent block for each name.
+    fn write_configs(dir: &Path, names: &[&str]) {
+        for name in names {
+            fs::write(dir.join(format!("{}.conf", name)), format!("{} {{\n    persist;\n}}\n", name)).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_resolve_list_containers() {
+        let list_containers = resolve_list_containers(None, None, None, None);
+        assert_eq!(list_containers.config_dir, PathBuf::from("examples"));
+        assert_eq!(list_containers.config_glob, None);
+
+        let list_containers = resolve_list_containers(Some("/etc/jail.conf.d"), None, Some(""), None);
+        assert_eq!(list_containers.config_dir, PathBuf::from("/etc/jail.conf.d"));
+        assert_eq!(list_containers.config_glob, None);
+
+        let list_containers = resolve_list_containers(Some("/etc/jail.conf.d"), Some(Path::new("/jails")), Some("/env/*.conf"), Some("/jails/**/*.conf"));
+        assert_eq!(list_containers.config_dir, PathBuf::from("/jails"));
+        assert_eq!(list_containers.config_glob.as_deref(), Some("/jails/**/*.conf"));
+        assert_eq!(list_containers.watched_dir(), PathBuf::from("/jails"));
+    }
+
+    #[test]
+    fn test_resolve_listen_addr() {
+        assert_eq!(resolve_listen_addr(None, None).unwrap(), ListenAddr::Tcp("127.0.0.1:50051".parse().unwrap()));
+        assert_eq!(resolve_listen_addr(Some("0.0.0.0:6000"), None).unwrap(), ListenAddr::Tcp("0.0.0.0:6000".parse().unwrap()));
+        assert_eq!(resolve_listen_addr(Some("0.0.0.0:6000"), Some("[::1]:7000")).unwrap(), ListenAddr::Tcp("[::1]:7000".parse().unwrap()));
+        assert_eq!(resolve_listen_addr(Some("unix:/var/run/conmand.sock"), None).unwrap(), ListenAddr::Unix(PathBuf::from("/var/run/conmand.sock")));
+        assert!(resolve_listen_addr(Some("unix:"), None).is_err());
+
+        let error = resolve_listen_addr(Some("localhost"), None).unwrap_err();
+        assert!(error.to_string().starts_with("invalid listen address \"localhost\""));
+        assert!(resolve_listen_addr(None, Some("127.0.0.1:99999")).is_err());
+    }
+
+    #[test]
+    fn test_check_configurations() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("frodo.conf"), "frodo {\n    persist;\n}\nsam {\n}\n").unwrap();
+        fs::write(dir.path().join("gimli.conf"), "gimli {\n    persist;\n    @bad;\n}\n").unwrap();
+        fs::write(dir.path().join("notes.txt"), "not a configuration").unwrap();
+
+        let mut out = Vec::new();
+        let failures = check_configurations(dir.path(), &mut out).unwrap();
+        assert_eq!(failures, 1);
+
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines, vec![
+            format!("OK {} (2 containers)", dir.path().join("frodo.conf").display()),
+            format!("ERROR {}: unexpected character '@' at line 3, column 5", dir.path().join("gimli.conf").display()),
+        ]);
+
+        fs::remove_file(dir.path().join("gimli.conf")).unwrap();
+        assert_eq!(check_configurations(dir.path(), &mut Vec::new()).unwrap(), 0);
+
+        let args = Args::try_parse_from(["conmand", "--check", "/usr/local/etc/jail.conf.d"]).unwrap();
+        assert_eq!(args.check, Some(PathBuf::from("/usr/local/etc/jail.conf.d")));
+    }
+
+    type ServerHandle = tokio::task::JoinHandle<Result<(), Box<dyn std::error::Error + Send + Sync>>>;
+
+    // Start the server on an ephemeral port and wait until it accepts connections.
+    async fn spawn_server() -> (SocketAddr, tokio::sync::oneshot::Sender<()>, ServerHandle) {
+        spawn_server_with_token(None).await
+    }
+
+    async fn spawn_server_with_token(token: Option<&str>) -> (SocketAddr, tokio::sync::oneshot::Sender<()>, ServerHandle) {
+        let addr = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let (trigger, shutdown) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(run_server(ListenAddr::Tcp(addr), ListContainers::new(), token.map(str::to_string), async {
+            shutdown.await.ok();
+        }));
+
+        let mut connected = false;
+        for _ in 0..50 {
+            if tokio::net::TcpStream::connect(addr).await.is_ok() {
+                connected = true;
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        assert!(connected);
+
+        (addr, trigger, server)
+    }
+
+    #[tokio::test]
+    async fn test_run_server_shutdown() {
+        let (_, trigger, server) = spawn_server().await;
+
+        trigger.send(()).unwrap();
+        let result = tokio::time::timeout(std::time::Duration::from_secs(5), server).await;
+        assert!(result.unwrap().unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_unix_socket() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("conmand.sock");
+        // A stale socket from an earlier run is replaced
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+
+        let (trigger, shutdown) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(run_server(ListenAddr::Unix(path.clone()), ListContainers::new(), None, async {
+            shutdown.await.ok();
+        }));
+
+        let mut channel = None;
+        for _ in 0..50 {
+            let path = path.clone();
+            let connector = tower::service_fn(move |_: tonic::transport::Uri| {
+                let path = path.clone();
+                async move {
+                    let stream = tokio::net::UnixStream::connect(path).await?;
+                    Ok::<_, std::io::Error>(hyper_util::rt::TokioIo::new(stream))
+                }
+            });
+            if let Ok(connected) = tonic::transport::Endpoint::from_static("http://localhost")
+                .connect_with_connector(connector).await {
+                channel = Some(connected);
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+
+        let mut client = generated::hello::hello_world_client::HelloWorldClient::new(channel.unwrap());
+        let request = HelloRequest { name: "gimli".to_string() };
+        let reply = client.say_hello(request).await.unwrap().into_inner();
+        assert_eq!(reply.message, "Hello, gimli!");
+
+        trigger.send(()).unwrap();
+        let result = tokio::time::timeout(std::time::Duration::from_secs(5), server).await;
+        assert!(result.unwrap().unwrap().is_ok());
+    }
+
+    async fn get_containers_with_token(addr: SocketAddr, token: Option<&str>) -> Result<usize, Status> {
+        use generated::container::list_containers_client::ListContainersClient;
+
+        let channel = tonic::transport::Endpoint::from_shared(format!("http://{}", addr)).unwrap()
+            .connect().await.unwrap();
+        let mut client = ListContainersClient::new(channel);
+        let mut request = Request::new(GetContainersRequest::default());
+        if let Some(token) = token {
+            request.metadata_mut().insert("authorization", format!("Bearer {}", token).parse().unwrap());
+        }
+        Ok(client.get_containers(request).await?.into_inner().containers.len())
+    }
+
+    #[tokio::test]
+    async fn test_auth_interceptor() {
+        let (addr, trigger, _) = spawn_server_with_token(Some("secret")).await;
+
+        assert_eq!(get_containers_with_token(addr, Some("secret")).await.unwrap(), 3);
+        let status = get_containers_with_token(addr, Some("wrong")).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated);
+        let status = get_containers_with_token(addr, None).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated);
+        trigger.send(()).unwrap();
+
+        let (addr, trigger, _) = spawn_server().await;
+        assert_eq!(get_containers_with_token(addr, None).await.unwrap(), 3);
+        trigger.send(()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_health_service() {
+        use tonic_health::pb::health_check_response::ServingStatus;
+        use tonic_health::pb::health_client::HealthClient;
+        use tonic_health::pb::HealthCheckRequest;
+
+        let (addr, trigger, _) = spawn_server().await;
+
+        let channel = tonic::transport::Endpoint::from_shared(format!("http://{}", addr)).unwrap()
+            .connect().await.unwrap();
+        let mut client = HealthClient::new(channel);
+        let request = HealthCheckRequest { service: "container.ListContainers".to_string() };
+        let response = client.check(request).await.unwrap().into_inner();
+        assert_eq!(response.status(), ServingStatus::Serving);
+
+        trigger.send(()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_reflection_service() {
+        use tonic_reflection::pb::v1::server_reflection_client::ServerReflectionClient;
+        use tonic_reflection::pb::v1::server_reflection_request::MessageRequest;
+        use tonic_reflection::pb::v1::server_reflection_response::MessageResponse;
+        use tonic_reflection::pb::v1::ServerReflectionRequest;
+
+        let (addr, trigger, _) = spawn_server().await;
+
+        let channel = tonic::transport::Endpoint::from_shared(format!("http://{}", addr)).unwrap()
+            .connect().await.unwrap();
+        let mut client = ServerReflectionClient::new(channel);
+        let request = ServerReflectionRequest {
+            host: String::new(),
+            message_request: Some(MessageRequest::ListServices(String::new())),
+        };
+        let mut responses = client.server_reflection_info(tokio_stream::once(request)).await.unwrap().into_inner();
+        let response = responses.message().await.unwrap().unwrap();
+
+        let services: Vec<String> = match response.message_response {
+            Some(MessageResponse::ListServicesResponse(list)) => list.service.into_iter().map(|s| s.name).collect(),
+            other => panic!("unexpected reflection response {:?}", other),
+        };
+        assert!(services.contains(&"hello.HelloWorld".to_string()));
+        assert!(services.contains(&"container.ListContainers".to_string()));
+
+        trigger.send(()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_running_status() {
+        let dir = tempfile::tempdir().unwrap();
+        let jls = stub_command(dir.path(), "jls", "cat <<'EOF'\njid=1 name=gimli host.hostname=gimli\njid=2 name=web host.hostname=elf.local\nEOF\n");
+        let list_containers = ListContainers::new().with_jls(JlsCommand::new().with_binary(jls));
+
+        let running_jails = list_containers.running_jails().await;
+
+        let gimli = Configuration::builder().name("gimli").build();
+        let aragorn = Configuration::builder().name("aragorn").build();
+        let legolas = Configuration::builder().name("legolas").value("host.hostname", "elf.local").build();
+
+        assert!(ListContainers::configuration_to_container(&gimli, &running_jails).running);
+        assert!(!ListContainers::configuration_to_container(&aragorn, &running_jails).running);
+        assert!(ListContainers::configuration_to_container(&legolas, &running_jails).running);
+    }
+
+    #[tokio::test]
+    async fn test_container_jid() {
+        let dir = tempfile::tempdir().unwrap();
+        let jls = stub_command(dir.path(), "jls", "echo 'jid=7 name=gimli host.hostname=gimli'\n");
+        let list_containers = ListContainers::new().with_jls(JlsCommand::new().with_binary(jls));
+
+        let running_jails = list_containers.running_jails().await;
+
+        let gimli = Configuration::builder().name("gimli").value("ip4.addr", "192.168.0.61").build();
+        let aragorn = Configuration::builder().name("aragorn").build();
+
+        assert_eq!(ListContainers::configuration_to_container(&gimli, &running_jails).id, Some(7));
+        assert_eq!(ListContainers::configuration_to_container(&aragorn, &running_jails).id, None);
+    }
+
+    #[test]
+    fn test_container_round_trip() {
+        let container = Container {
+            name: "gimli".to_string(),
+            id: None,
+            dataset: "/jails/gimli".to_string(),
+            addresses: vec!["10.0.0.1".to_string(), "10.0.0.2".to_string()],
+            running: false,
+        };
+
+        let configuration = Configuration::from_container(&container);
+        assert_eq!(configuration.get_string("host.hostname"), Some("gimli"));
+        assert_eq!(ListContainers::configuration_to_container(&configuration, &HashMap::new()), container);
+
+        let text = configuration.to_config_string();
+        let parsed = parser::config_parser::ConfigParser::new().parse_content(&text).unwrap();
+        assert_eq!(ListContainers::configuration_to_container(&parsed, &HashMap::new()), container);
+    }
+
+    #[tokio::test]
+    async fn test_create_container() {
+        use generated::container::list_containers_server::ListContainers as _;
+
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("gimli.conf"), "gimli {\n    mount.devfs;\n}\n").unwrap();
+        let list_containers = ListContainers::new().with_dir(dir.path());
+
+        let directives = [
+            ("path".to_string(), "/usr/local/jails/containers/frodo".to_string()),
+            ("exec.start".to_string(), "/bin/sh /etc/rc".to_string()),
+            ("mount.devfs".to_string(), String::new()),
+        ].into_iter().collect();
+        let request = Request::new(CreateContainerRequest { name: "frodo".to_string(), directives });
+        let reply = list_containers.create_container(request).await.unwrap().into_inner();
+        assert!(reply.success);
+
+        let path = dir.path().join("frodo.conf");
+        assert_eq!(reply.message, path.display().to_string());
+        let configuration = parser::config_parser::ConfigParser::new().parse_file(&path).unwrap();
+        assert_eq!(configuration.name, "frodo");
+        let item = &configuration.directives[0];
+        assert_eq!(item.get_string("exec.start"), Some("/bin/sh /etc/rc"));
+        assert_eq!(configuration.directives[1].get_bool("mount.devfs"), Some(true));
+        assert_eq!(configuration.directives[2].get_string("path"), Some("/usr/local/jails/containers/frodo"));
+
+        let request = Request::new(CreateContainerRequest { name: "frodo".to_string(), ..Default::default() });
+        let status = list_containers.create_container(request).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::AlreadyExists);
+
+        let request = Request::new(CreateContainerRequest { name: "gimli".to_string(), ..Default::default() });
+        let status = list_containers.create_container(request).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::AlreadyExists);
+
+        let request = Request::new(CreateContainerRequest { name: "../frodo".to_string(), ..Default::default() });
+        let status = list_containers.create_container(request).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+
+        let request = Request::new(CreateContainerRequest { name: "*".to_string(), ..Default::default() });
+        let status = list_containers.create_container(request).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        assert!(!dir.path().join("*.conf").exists());
+    }
+
+    #[tokio::test]
+    async fn test_create_container_rejects_injection() {
+        use generated::container::list_containers_server::ListContainers as _;
+
+        let dir = tempfile::tempdir().unwrap();
+        let list_containers = ListContainers::new().with_dir(dir.path());
+
+        let bad_key = [("persist; exec.start=\"/bin/evil\"".to_string(), String::new())].into_iter().collect();
+        let bad_value = [("path".to_string(), "x\"; exec.prestart=\"/bin/sh -c evil".to_string())].into_iter().collect();
+        for directives in [bad_key, bad_value] {
+            let request = Request::new(CreateContainerRequest { name: "frodo".to_string(), directives });
+            let status = list_containers.create_container(request).await.unwrap_err();
+            assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        }
+
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_stream_containers() {
+        use generated::container::list_containers_server::ListContainers as _;
+        use tokio_stream::StreamExt;
+
+        let list_containers = ListContainers::new();
+
+        let request = Request::new(GetContainersRequest::default());
+        let stream = list_containers.stream_containers(request).await.unwrap().into_inner();
+        let streamed: Vec<Container> = stream.map(|container| container.unwrap()).collect().await;
+
+        let request = Request::new(GetContainersRequest::default());
+        let containers = list_containers.get_containers(request).await.unwrap().into_inner().containers;
+
+        let streamed: Vec<String> = streamed.into_iter().map(|container| container.name).collect();
+        let expected: Vec<String> = containers.into_iter().map(|container| container.name).collect();
+        assert_eq!(streamed.len(), 3);
+        assert_eq!(streamed, expected);
+    }
+
+    #[tokio::test]
+    async fn test_stream_containers_from_cache() {
+        use generated::container::list_containers_server::ListContainers as _;
+        use tokio_stream::StreamExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        write_configs(dir.path(), &["sam", "frodo"]);
+        fs::write(dir.path().join("zz.conf"), "frodo {\n    path = \"/jails/baggins\";\n}\n").unwrap();
+        let list_containers = ListContainers::new().with_dir(dir.path());
+        list_containers.reload();
+
+        // Files changed since the last reload aren't streamed, as they aren't listed either
+        write_configs(dir.path(), &["pippin"]);
+        let request = Request::new(GetContainersRequest::default());
+        let stream = list_containers.stream_containers(request).await.unwrap().into_inner();
+        let streamed: Vec<Container> = stream.map(|container| container.unwrap()).collect().await;
+
+        let names: Vec<&str> = streamed.iter().map(|container| container.name.as_str()).collect();
+        assert_eq!(names, vec!["frodo", "sam"]);
+        assert_eq!(streamed[0].dataset, "zpool/datasets/containers/frodo");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_say_hello_stream() {
+        use generated::hello::hello_world_server::HelloWorld as _;
+
+        let hello_world = MyHelloWorld::default();
+        let request = Request::new(HelloStreamRequest { name: "Frodo".to_string(), interval_seconds: 1 });
+        let stream = hello_world.say_hello_stream(request).await.unwrap().into_inner();
+
+        // The clock is paused, so the runtime skips ahead to each tick instead of sleeping
+        let started = tokio::time::Instant::now();
+        let replies: Vec<String> = stream.take(3).map(|reply| reply.unwrap().message).collect().await;
+
+        assert_eq!(replies, vec!["Hello, Frodo! (1)", "Hello, Frodo! (2)", "Hello, Frodo! (3)"]);
+        assert_eq!(started.elapsed(), Duration::from_secs(2));
+
+        let request = Request::new(HelloStreamRequest { name: "Frodo".to_string(), interval_seconds: 0 });
+        let status = hello_world.say_hello_stream(request).await.err().unwrap();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+    }
+
+    #[tokio::test]
+    async fn test_get_containers_filter() {
+        use generated::container::list_containers_server::ListContainers as _;
+
+        let list_containers = ListContainers::new();
+
+        let request = Request::new(GetContainersRequest { names: vec!["gimli".to_string()], ..Default::default() });
+        let containers = list_containers.get_containers(request).await.unwrap().into_inner().containers;
+        assert_eq!(containers.len(), 1);
+        assert_eq!(containers[0].name, "gimli");
+
+        let request = Request::new(GetContainersRequest::default());
+        let containers = list_containers.get_containers(request).await.unwrap().into_inner().containers;
+        assert_eq!(containers.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_get_containers_with_dir() {
+        use generated::container::list_containers_server::ListContainers as _;
+
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("frodo.conf"), "frodo {\n    path = \"/jails/frodo\";\n}\n").unwrap();
+        fs::write(dir.path().join("notes.txt"), "sam {\n}\n").unwrap();
+        let list_containers = ListContainers::new().with_dir(dir.path());
+
+        let request = Request::new(GetContainersRequest::default());
+        let containers = list_containers.get_containers(request).await.unwrap().into_inner().containers;
+        assert_eq!(containers.len(), 1);
+        assert_eq!(containers[0].name, "frodo");
+        assert_eq!(containers[0].dataset, "/jails/frodo");
+    }
+
+    #[test]
+    fn test_parse_spans() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("frodo.conf"), "frodo {\n    persist;\n}\n").unwrap();
+        fs::write(dir.path().join("sam.conf"), "sam {\n    persist;\n}\n").unwrap();
+        let list_containers = ListContainers::new().with_dir(dir.path());
+
+        let recorder = SpanRecorder::default();
+        let subscriber = tracing_subscriber::registry().with(recorder.clone());
+        tracing::subscriber::with_default(subscriber, || list_containers.reload());
+        assert_eq!(list_containers.snapshot().unwrap().len(), 2);
+
+        let spans = recorder.0.lock().unwrap();
+        let mut parsed: Vec<String> = spans.iter()
+            .filter(|(name, _)| name == "parse_jail_conf")
+            .map(|(_, path)| path.clone())
+            .collect();
+        parsed.sort();
+        assert_eq!(parsed, vec![
+            dir.path().join("frodo.conf").display().to_string(),
+            dir.path().join("sam.conf").display().to_string(),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn test_get_containers_skips_bad_file() {
+        use generated::container::list_containers_server::ListContainers as _;
+
+        capture_warnings();
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("frodo.conf"), "frodo {\n    path = \"/jails/frodo\";\n}\n").unwrap();
+        fs::write(dir.path().join("sam.conf"), "sam {\n    host = new;\n    host = inherit;\n}\n").unwrap();
+        fs::write(dir.path().join("pippin.conf"), "pippin {\n    persist;\n}\n").unwrap();
+        let list_containers = ListContainers::new().with_dir(dir.path());
+
+        let request = Request::new(GetContainersRequest::default());
+        let containers = list_containers.get_containers(request).await.unwrap().into_inner().containers;
+        let names: Vec<String> = containers.into_iter().map(|container| container.name).collect();
+        assert_eq!(names, vec!["frodo".to_string(), "pippin".to_string()]);
+
+        let bad = dir.path().join("sam.conf").display().to_string();
+        assert!(WARNINGS.lock().unwrap().iter().any(|warning| {
+            warning.contains(&bad) && warning.contains("duplicate directive \"host\"")
+        }));
+    }
+
+    #[test]
+    fn test_reload_skips_duplicate_names() {
+        capture_warnings();
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a.conf"), "frodo {\n    path = \"/jails/frodo\";\n}\n").unwrap();
+        fs::write(dir.path().join("b.conf"), "frodo {\n    path = \"/jails/baggins\";\n}\nsam {\n}\n").unwrap();
+        let list_containers = ListContainers::new().with_dir(dir.path());
+        list_containers.reload();
+
+        let configurations = list_containers.snapshot().unwrap();
+        let names: Vec<&str> = configurations.iter().map(|configuration| configuration.name.as_str()).collect();
+        assert_eq!(names, vec!["frodo", "sam"]);
+        assert_eq!(configurations[0].get_string("path"), Some("/jails/frodo"));
+
+        let skipped = dir.path().join("b.conf").display().to_string();
+        assert!(WARNINGS.lock().unwrap().iter().any(|warning| {
+            warning.contains("frodo") && warning.contains(&skipped) && warning.contains("already defined")
+        }));
+    }
+
+    #[tokio::test]
+    async fn test_cache_invalidated_by_create() {
+        use generated::container::list_containers_server::ListContainers as _;
+
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("gimli.conf"), "gimli {\n    persist;\n}\n").unwrap();
+        let list_containers = ListContainers::new().with_dir(dir.path());
+        assert_eq!(list_containers.cached_configurations().await.len(), 1);
+
+        // Files changed behind the server's back aren't seen until a reload
+        fs::write(dir.path().join("sam.conf"), "sam {\n    persist;\n}\n").unwrap();
+        assert_eq!(list_containers.cached_configurations().await.len(), 1);
+        list_containers.reload();
+        assert_eq!(list_containers.cached_configurations().await.len(), 2);
+
+        let request = Request::new(CreateContainerRequest { name: "frodo".to_string(), ..Default::default() });
+        list_containers.create_container(request).await.unwrap();
+        let request = Request::new(GetContainersRequest::default());
+        let containers = list_containers.get_containers(request).await.unwrap().into_inner().containers;
+        let names: Vec<String> = containers.into_iter().map(|container| container.name).collect();
+        assert_eq!(names, vec!["frodo".to_string(), "gimli".to_string(), "sam".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_get_containers_sorted() {
+        use generated::container::list_containers_server::ListContainers as _;
+
+        let dir = tempfile::tempdir().unwrap();
+        write_configs(dir.path(), &["pippin", "aragorn", "merry"]);
+        let list_containers = ListContainers::new().with_dir(dir.path());
+
+        let request = Request::new(GetContainersRequest::default());
+        let containers = list_containers.get_containers(request).await.unwrap().into_inner().containers;
+        let names: Vec<String> = containers.into_iter().map(|container| container.name).collect();
+        assert_eq!(names, vec!["aragorn".to_string(), "merry".to_string(), "pippin".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_get_containers_pages() {
+        use generated::container::list_containers_server::ListContainers as _;
+
+        let dir = tempfile::tempdir().unwrap();
+        write_configs(dir.path(), &["sam", "frodo", "gimli"]);
+        let list_containers = ListContainers::new().with_dir(dir.path());
+
+        let request = Request::new(GetContainersRequest { page_size: 2, ..Default::default() });
+        let reply = list_containers.get_containers(request).await.unwrap().into_inner();
+        let names: Vec<String> = reply.containers.into_iter().map(|container| container.name).collect();
+        assert_eq!(names, vec!["frodo".to_string(), "gimli".to_string()]);
+        assert!(!reply.next_page_token.is_empty());
+
+        let request = Request::new(GetContainersRequest {
+            page_size: 2,
+            page_token: reply.next_page_token,
+            ..Default::default()
+        });
+        let reply = list_containers.get_containers(request).await.unwrap().into_inner();
+        let names: Vec<String> = reply.containers.into_iter().map(|container| container.name).collect();
+        assert_eq!(names, vec!["sam".to_string()]);
+        assert!(reply.next_page_token.is_empty());
+
+        let request = Request::new(GetContainersRequest { page_token: "bogus".to_string(), ..Default::default() });
+        let status = list_containers.get_containers(request).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+    }
+
+    #[tokio::test]
+    async fn test_watch_reloads() {
+        use generated::container::list_containers_server::ListContainers as _;
+
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("gimli.conf"), "gimli {\n    persist;\n}\n").unwrap();
+        let list_containers = ListContainers::new().with_dir(dir.path());
+        list_containers.reload();
+        let _watcher = list_containers.watch(Duration::from_millis(50)).unwrap();
+
+        fs::write(dir.path().join("frodo.conf"), "frodo {\n    persist;\n}\n").unwrap();
+        let names = tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                let request = Request::new(GetContainersRequest::default());
+                let containers = list_containers.get_containers(request).await.unwrap().into_inner().containers;
+                if containers.len() == 2 {
+                    break containers.into_iter().map(|container| container.name).collect::<Vec<String>>();
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        }).await.expect("the new container was not picked up");
+        assert_eq!(names, vec!["frodo".to_string(), "gimli".to_string()]);
+    }
+
+    #[test]
+    fn test_watched_dir() {
+        let list_containers = ListContainers::new().with_dir("/etc/jail.conf.d");
+        assert_eq!(list_containers.watched_dir(), PathBuf::from("/etc/jail.conf.d"));
+        assert_eq!(list_containers.clone().with_glob("/usr/local/etc/jails/**/*.conf").watched_dir(), PathBuf::from("/usr/local/etc/jails"));
+        assert_eq!(list_containers.clone().with_glob("/jails/web-[0-9]/jail.conf").watched_dir(), PathBuf::from("/jails"));
+        assert_eq!(list_containers.with_glob("*.conf").watched_dir(), PathBuf::from("."));
+    }
+
+    #[tokio::test]
+    async fn test_watch_glob_reloads() {
+        use generated::container::list_containers_server::ListContainers as _;
+
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("shire")).unwrap();
+        let pattern = format!("{}/shire/**/*.conf", dir.path().display());
+        let list_containers = ListContainers::new().with_dir(dir.path().join("missing")).with_glob(pattern);
+        list_containers.reload();
+        let _watcher = list_containers.watch(Duration::from_millis(50)).unwrap();
+
+        fs::write(dir.path().join("shire/sam.conf"), "sam {\n    persist;\n}\n").unwrap();
+        let names = tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                let request = Request::new(GetContainersRequest::default());
+                let containers = list_containers.get_containers(request).await.unwrap().into_inner().containers;
+                if !containers.is_empty() {
+                    break containers.into_iter().map(|container| container.name).collect::<Vec<String>>();
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        }).await.expect("the new container was not picked up");
+        assert_eq!(names, vec!["sam".to_string()]);
+    }
+
+    #[test]
+    fn test_cache_reload_while_reading() {
+        let dir = tempfile::tempdir().unwrap();
+        write_configs(dir.path(), &["frodo", "sam", "pippin", "merry"]);
+        let list_containers = Arc::new(ListContainers::new().with_dir(dir.path()));
+        list_containers.reload();
+
+        let reloader = {
+            let list_containers = list_containers.clone();
+            std::thread::spawn(move || {
+                for _ in 0..50 {
+                    list_containers.reload();
+                }
+            })
+        };
+        let readers: Vec<_> = (0..4).map(|_| {
+            let list_containers = list_containers.clone();
+            std::thread::spawn(move || {
+                for _ in 0..50 {
+                    let names: Vec<String> = list_containers.snapshot().unwrap().into_iter()
+                        .map(|configuration| configuration.name)
+                        .collect();
+                    assert_eq!(names, vec!["frodo", "merry", "pippin", "sam"]);
+                }
+            })
+        }).collect();
+
+        reloader.join().unwrap();
+        for reader in readers {
+            reader.join().unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_containers_with_glob() {
+        use generated::container::list_containers_server::ListContainers as _;
+
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("shire/bag-end")).unwrap();
+        fs::write(dir.path().join("frodo.conf"), "frodo {\n}\n").unwrap();
+        fs::write(dir.path().join("shire/sam.conf"), "sam {\n}\n").unwrap();
+        fs::write(dir.path().join("shire/bag-end/bilbo.conf"), "bilbo {\n}\n").unwrap();
+        fs::write(dir.path().join("shire/bag-end/notes.txt"), "gollum {\n}\n").unwrap();
+        let pattern = format!("{}/shire/**/*.conf", dir.path().display());
+        let list_containers = ListContainers::new().with_dir(dir.path()).with_glob(pattern);
+
+        let request = Request::new(GetContainersRequest::default());
+        let containers = list_containers.get_containers(request).await.unwrap().into_inner().containers;
+        let mut names: Vec<String> = containers.into_iter().map(|container| container.name).collect();
+        names.sort();
+        assert_eq!(names, vec!["bilbo".to_string(), "sam".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_start_container() {
+        use generated::container::list_containers_server::ListContainers as _;
+
+        let dir = tempfile::tempdir().unwrap();
+        let jail = stub_command(dir.path(), "jail", "echo \"$2: created\"\n");
+        let list_containers = ListContainers::new().with_jail(JailCommand::new().with_binary(jail));
+
+        let request = Request::new(StartContainerRequest { name: "gimli".to_string() });
+        let reply = list_containers.start_container(request).await.unwrap().into_inner();
+        assert!(reply.success);
+        assert_eq!(reply.message, "gimli: created");
+
+        let request = Request::new(StartContainerRequest { name: "sauron".to_string() });
+        let status = list_containers.start_container(request).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::NotFound);
+
+        let jail = stub_command(dir.path(), "failing-jail", "echo 'jail: gimli: already exists' >&2\nexit 1\n");
+        let list_containers = ListContainers::new().with_jail(JailCommand::new().with_binary(jail));
+
+        let request = Request::new(StartContainerRequest { name: "gimli".to_string() });
+        let status = list_containers.start_container(request).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Internal);
+        assert!(status.message().ends_with("jail: gimli: already exists"));
+    }
+
+    #[tokio::test]
+    async fn test_stop_container() {
+        use generated::container::list_containers_server::ListContainers as _;
+
+        let dir = tempfile::tempdir().unwrap();
+        let jail = stub_command(dir.path(), "jail", "echo \"$2: removed\"\n");
+        let jls = stub_command(dir.path(), "jls", "echo 'jid=1 name=gimli'\n");
+        let list_containers = ListContainers::new()
+            .with_jls(JlsCommand::new().with_binary(jls))
+            .with_jail(JailCommand::new().with_binary(jail));
+
+        let request = Request::new(StopContainerRequest { name: "gimli".to_string() });
+        let reply = list_containers.stop_container(request).await.unwrap().into_inner();
+        assert!(reply.success);
+        assert_eq!(reply.message, "gimli: removed");
+
+        let request = Request::new(StopContainerRequest { name: "sauron".to_string() });
+        let status = list_containers.stop_container(request).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::NotFound);
+
+        let request = Request::new(StopContainerRequest { name: "aragorn".to_string() });
+        let status = list_containers.stop_container(request).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::FailedPrecondition);
+    }
+}
@@ -24,5 +24,99 @@
 
 pub mod config_item;
 pub mod config_parser;
+pub mod parse_error;
 pub mod parser_state;
-mod config;
\ No newline at end of file
+pub mod config;
+pub mod jail_conf;
+
+use std::path::Path;
+use crate::parser::config::Configuration;
+use crate::parser::config_parser::ConfigParser;
+use crate::parser::jail_conf::JailConf;
+use crate::parser::parse_error::ParseError;
+
+/// Read every container configuration from a jail.conf file with the state machine parser,
+/// following any `include` directives.  Each directive's values are split out as it is read,
+/// so the configurations are ready to query.  A `*` block is applied as a template to every
+/// other configuration read by this call, that is those in the file and the files it includes,
+/// and is not returned itself.  A template in one file does not apply to the jails of a file
+/// that is read separately, such as another file in the server's configuration directory.
+/// Each call is traced as a `parse_jail_conf` span at debug level, recording the path and the
+/// number of configurations read.
+///
+/// A parameter set at the top level of a file, outside of any block, applies to every jail as
+/// the `*` block's do.  The `$` variables defined at the top level of the file and the files it
+/// includes are expanded in the values, along with `$name` and the environment, once the
+/// templates are applied.  A variable with no value is left as it is.
+///
+/// * `path` - The path to the file
+#[tracing::instrument(level = "debug", skip_all, fields(path = %path.as_ref().display(), configurations))]
+pub fn parse_jail_conf<P: AsRef<Path>>(path: P) -> Result<Vec<Configuration>, ParseError> {
+    let JailConf { variables, configurations } = ConfigParser::new().parse_all_file_with_variables(path)?;
+    let (templates, configurations): (Vec<Configuration>, Vec<Configuration>) =
+        configurations.into_iter().partition(Configuration::is_template);
+    let mut jail_conf = JailConf { variables, configurations };
+    for template in &templates {
+        for configuration in &mut jail_conf.configurations {
+            configuration.apply_template(template);
+        }
+    }
+    jail_conf.interpolate(false)?;
+    tracing::Span::current().record("configurations", jail_conf.configurations.len());
+    Ok(jail_conf.configurations)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::config_item::ConfigValue;
+
+    #[test]
+    fn test_parse_jail_conf_template() -> Result<(), ParseError> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("jail.conf");
+        std::fs::write(&path, "* {\n\tpersist;\n}\nweb {\n\tpath = \"/jails/web\";\n}\n")?;
+
+        let configurations = parse_jail_conf(&path)?;
+        assert_eq!(configurations.len(), 1);
+        assert_eq!(configurations[0].name, "web");
+        assert_eq!(configurations[0].get_value("persist").as_deref(), Some(&ConfigValue::Boolean(true)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_jail_conf_variables() -> Result<(), ParseError> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("jail.conf");
+        std::fs::write(&path, "$root = \"/usr/local/jails\";\nweb {\n\tpath = \"$root/$name\";\n}\n")?;
+
+        let configurations = parse_jail_conf(&path)?;
+        assert_eq!(configurations.len(), 1);
+        assert_eq!(configurations[0].get_string("path"), Some("/usr/local/jails/web"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_jail_conf_top_level_parameters() -> Result<(), ParseError> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("jail.conf");
+        std::fs::write(&path, concat!(
+            "$root = \"/usr/local/jails\";\n",
+            "path = \"$root/$name\";\n",
+            "mount.devfs;\n",
+            "web {\n}\n",
+            "db {\n\tpath = \"/zroot/db\";\n}\n",
+        ))?;
+
+        let configurations = parse_jail_conf(&path)?;
+        assert_eq!(configurations.len(), 2);
+        assert_eq!(configurations[0].get_string("path"), Some("/usr/local/jails/web"));
+        assert_eq!(configurations[0].get_value("mount.devfs").as_deref(), Some(&ConfigValue::Boolean(true)));
+        assert_eq!(configurations[1].get_string("path"), Some("/zroot/db"));
+        assert_eq!(configurations[1].get_value("mount.devfs").as_deref(), Some(&ConfigValue::Boolean(true)));
+
+        Ok(())
+    }
+}
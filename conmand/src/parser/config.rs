@@ -22,19 +22,126 @@
 //! SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 //! 
 
-use crate::parser::config_item::ConfigItem;
+use std::borrow::Cow;
+use std::collections::HashMap;
+#[cfg(feature = "grpc")]
+use crate::generated::container::Container;
+use crate::parser::config_item::{ConfigItem, ConfigValue};
+use crate::parser::parse_error::{NameError, ParseError};
+use crate::parser::parser_state::ParserState;
 
 /// Configuration encapsulates the configuration of a container.  It is composed of a name
-/// followed by zero or more directives.
-#[derive(Debug, Default, Clone)]
+/// followed by zero or more directives.  A block may contain nested blocks, which are kept as
+/// child configurations in the order they were declared.  Comments are kept with the block
+/// they appear in as the byte offset of the '#' and the text that follows it on the line.
+#[derive(Debug, Default, Clone, PartialEq)]
 pub struct Configuration {
     pub name : String,
-    pub directives : Vec<ConfigItem>
+    pub directives : Vec<ConfigItem>,
+    pub children : Vec<Configuration>,
+    pub comments : Vec<(usize, String)>,
 }
 
 
+/// Builds a Configuration in code rather than by parsing text.  Each call adds one directive,
+/// in the order the calls are made.
+///
+/// ```
+/// use conmand::parser::config::Configuration;
+///
+/// let config = Configuration::builder()
+///     .name("web")
+///     .directive("persist")
+///     .value("ip4.addr", "10.0.0.1")
+///     .build();
+///
+/// assert_eq!(config.name, "web");
+/// assert_eq!(config.get_string("ip4.addr"), Some("10.0.0.1"));
+/// ```
+#[derive(Debug, Default)]
+pub struct ConfigurationBuilder {
+    configuration: Configuration,
+}
+
+impl ConfigurationBuilder {
+
+    /// Set the container name.
+    ///
+    /// * `name` - The name of the container
+    pub fn name(mut self, name: &str) -> Self {
+        self.configuration.set_name(name);
+        self
+    }
+
+    /// Add a bare directive, such as `persist`.
+    ///
+    /// * `key` - The directive name.
+    pub fn directive(mut self, key: &str) -> Self {
+        let mut item = ConfigItem::new(key);
+        item.name = key.to_string();
+        item.add_value(key, ConfigValue::Boolean(true));
+        self.configuration.add_directive(&item);
+        self
+    }
+
+    /// Add a directive that assigns a string value.
+    ///
+    /// * `key` - The directive name.
+    /// * `value` - The value assigned to the directive.
+    pub fn value(mut self, key: &str, value: &str) -> Self {
+        let mut item = ConfigItem::new(format!("{} = {}", key, Configuration::quote(value)));
+        item.name = key.to_string();
+        item.add_value(key, ConfigValue::String(value.to_string()));
+        self.configuration.add_directive(&item);
+        self
+    }
+
+    /// Add a directive that assigns a list of values, such as `ip4.addr = 10.0.0.1, 10.0.0.2`.
+    ///
+    /// * `key` - The directive name.
+    /// * `values` - The values assigned to the directive, in order.
+    pub fn list(mut self, key: &str, values: &[String]) -> Self {
+        let quoted: Vec<String> = values.iter().map(|value| Configuration::quote(value)).collect();
+        let mut item = ConfigItem::new(format!("{} = {}", key, quoted.join(", ")));
+        item.name = key.to_string();
+        item.add_value(key, ConfigValue::Array(values.to_vec()));
+        self.configuration.add_directive(&item);
+        self
+    }
+
+    /// Finish building and return the configuration.
+    pub fn build(self) -> Configuration {
+        self.configuration
+    }
+}
+
 impl Configuration {
 
+    /// Start building a configuration in code.
+    pub fn builder() -> ConfigurationBuilder {
+        ConfigurationBuilder::default()
+    }
+
+    /// Build the configuration for a container reported by the gRPC service.  The dataset
+    /// becomes `path`, the addresses become `ip4.addr`, and the container name is used as the
+    /// `host.hostname`.  The id and running flag describe a running jail rather than its
+    /// configuration, so they are not carried over.
+    ///
+    /// * `container` - The container to configure.
+    #[cfg(feature = "grpc")]
+    pub fn from_container(container: &Container) -> Configuration {
+        let builder = Configuration::builder()
+            .name(&container.name)
+            .value("path", &container.dataset)
+            .value("host.hostname", &container.name);
+
+        if container.addresses.is_empty() {
+            builder.build()
+        } else {
+            builder.list("ip4.addr", &container.addresses).build()
+        }
+    }
+
     /// Set the container name.
     ///
     /// * `name` - The name of the container
@@ -42,17 +149,437 @@ impl Configuration {
         self.name = name.to_string();
     }
 
+    /// True if the block has no name, as in `{ persist; }`.  An anonymous block is a fragment
+    /// of a configuration, and is given its name by whatever includes it.
+    pub fn is_anonymous(&self) -> bool {
+        self.name.is_empty()
+    }
+
+    /// True if this is the `*` block, whose directives are the defaults for every jail.
+    pub fn is_template(&self) -> bool {
+        self.name == "*"
+    }
+
+    /// Check that the container name is a legal jail name.  The name is written into both a
+    /// file name and a block header, so it is checked against the same rule the parser uses to
+    /// read a block name: it must start with a letter or digit, followed by letters, digits,
+    /// `-` or `_`.  Anything else, such as `.`, which jail(8) uses to separate the names of
+    /// hierarchical jails, `/` or the jail.conf punctuation, is rejected.  A `*` is only
+    /// allowed as the whole name of the template block.
+    pub fn validate_name(&self) -> Result<(), NameError> {
+        if self.name.is_empty() {
+            return Err(NameError::Empty);
+        }
+        if self.is_template() {
+            return Ok(());
+        }
+
+        let mut state = ParserState::Starting;
+        for token in self.name.chars() {
+            state = state.next_state(token);
+            if token == '*' || state != ParserState::Name {
+                return Err(NameError::InvalidChar(token));
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that no key is assigned more than once in the block or in any of its nested
+    /// blocks.  A `+=` adds to an earlier assignment, so it is not counted as a duplicate.
+    /// Returns `ParseError::DuplicateKey` for the first key that is assigned again.
+    pub fn check_duplicates(&self) -> Result<(), ParseError> {
+        let mut assigned: Vec<&str> = Vec::new();
+        for directive in &self.directives {
+            let key = directive.name.as_str();
+            if key.is_empty() || Self::appends(directive) {
+                continue;
+            }
+            if assigned.contains(&key) {
+                return Err(ParseError::DuplicateKey { block: self.name.clone(), key: key.to_string() });
+            }
+            assigned.push(key);
+        }
+
+        self.children.iter().try_for_each(Configuration::check_duplicates)
+    }
+
     /// Add a directive to the container.
     ///
     /// * `directive` - A new directive to add.
     pub fn add_directive(&mut self, directive: &ConfigItem) {
         self.directives.push(directive.clone());
     }
+
+    /// The number of directives in the block, not counting nested blocks.
+    pub fn len(&self) -> usize {
+        self.directives.len()
+    }
+
+    /// True if the block has no directives.
+    pub fn is_empty(&self) -> bool {
+        self.directives.is_empty()
+    }
+
+    /// Get a directive by its position in the block.
+    ///
+    /// * `index` - The position of the directive, starting at 0.
+    pub fn get_directive(&self, index: usize) -> Option<&ConfigItem> {
+        self.directives.get(index)
+    }
+
+    /// Find the first directive whose raw text matches, ignoring surrounding whitespace.
+    ///
+    /// * `raw` - The directive text, such as `persist` or `host.hostname = "gimli"`.
+    pub fn find_directive(&self, raw: &str) -> Option<&ConfigItem> {
+        let raw = raw.trim();
+        self.directives.iter().find(|directive| directive.raw.trim() == raw)
+    }
+
+    /// Get the value the directives in the block assign to a key.  If more than one directive
+    /// assigns the key with `=`, the last one wins, as it does for jail(8).  Each `+=` after it
+    /// adds to that value, so `ip4.addr = 10.0.0.1; ip4.addr += 10.0.0.2;` is the list of both
+    /// addresses.  A value with nothing added to it is borrowed from its directive.
+    ///
+    /// * `key` - The directive name.
+    pub fn get_value(&self, key: &str) -> Option<Cow<'_, ConfigValue>> {
+        let assigned = self.directives.iter()
+            .rposition(|directive| directive.values.contains_key(key) && !Self::appends(directive));
+        let mut values = self.directives[assigned.unwrap_or(0)..].iter()
+            .filter_map(|directive| directive.values.get(key));
+
+        let first = values.next()?;
+        let mut appended = values.peekable();
+        if appended.peek().is_none() {
+            return Some(Cow::Borrowed(first));
+        }
+
+        let mut list = Self::value_list(first);
+        appended.for_each(|value| list.extend(Self::value_list(value)));
+        Some(Cow::Owned(ConfigValue::Array(list)))
+    }
+
+    /// Get a string value assigned in the block.  Returns `None` if the key is absent or the
+    /// value is not a string, including when a `+=` has made it a list.
+    ///
+    /// * `key` - The directive name.
+    pub fn get_string(&self, key: &str) -> Option<&str> {
+        match self.get_value(key) {
+            Some(Cow::Borrowed(ConfigValue::String(value))) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Get the values assigned in the block as a list.  A single string value is a list of one.
+    /// Returns `None` if the key is absent or the value is not a string or a list.
+    ///
+    /// * `key` - The directive name.
+    pub fn get_list(&self, key: &str) -> Option<Vec<String>> {
+        match self.get_value(key).as_deref() {
+            Some(ConfigValue::String(value)) => Some(vec![value.clone()]),
+            Some(ConfigValue::Array(values)) => Some(values.clone()),
+            _ => None,
+        }
+    }
+
+    /// The value as the list of strings a `+=` adds to.  A bare directive adds nothing.
+    fn value_list(value: &ConfigValue) -> Vec<String> {
+        match value {
+            ConfigValue::String(value) => vec![value.clone()],
+            ConfigValue::Array(values) => values.clone(),
+            ConfigValue::Integer(value) => vec![value.to_string()],
+            ConfigValue::Boolean(_) => vec![],
+        }
+    }
+
+    /// True if the directive adds to its key with `+=` rather than assigning it.
+    fn appends(directive: &ConfigItem) -> bool {
+        let key = directive.name.as_str();
+        !key.is_empty() && directive.raw.trim().strip_prefix(key)
+            .is_some_and(|rest| rest.trim_start().starts_with("+="))
+    }
+
+    /// Every key and value assigned by the directives in the block, in directive order, and
+    /// within a directive in the order its keys were first assigned.  Nested blocks are not
+    /// included.
+    pub fn iter_values(&self) -> impl Iterator<Item = (&str, &ConfigValue)> {
+        self.directives.iter().flat_map(|directive| directive.ordered_values())
+    }
+
+    /// Add a nested configuration block to the container.
+    ///
+    /// * `child` - The nested configuration to add.
+    pub fn add_child(&mut self, child: Configuration) {
+        self.children.push(child);
+    }
+
+    /// Layer another configuration over this one, as when overrides are applied to defaults.
+    /// Each directive in `other` replaces the directives here with the same key, keeping the
+    /// position of the first one, and directives with new keys are appended.  A list value
+    /// replaces the list here rather than adding to it, so an override can remove addresses.
+    /// Nested blocks with the same name are merged the same way, and new ones are appended.
+    /// The name is taken from `other` unless it is anonymous.
+    ///
+    /// * `other` - The configuration whose values take precedence.
+    pub fn merge(&mut self, other: &Configuration) {
+        if !other.is_anonymous() {
+            self.name = other.name.clone();
+        }
+
+        for directive in &other.directives {
+            let key = Self::directive_key(directive);
+            match self.directives.iter().position(|existing| Self::directive_key(existing) == key) {
+                Some(index) => {
+                    let mut position = 0;
+                    self.directives.retain(|existing| {
+                        position += 1;
+                        position - 1 == index || Self::directive_key(existing) != key
+                    });
+                    self.directives[index] = directive.clone();
+                },
+                None => self.directives.push(directive.clone()),
+            }
+        }
+
+        for child in &other.children {
+            match self.children.iter_mut().find(|existing| !child.is_anonymous() && existing.name == child.name) {
+                Some(existing) => existing.merge(child),
+                None => self.children.push(child.clone()),
+            }
+        }
+    }
+
+    /// Fill in the defaults from a template, such as the `*` block of a jail.conf file.  Each
+    /// directive in the template whose key is not set here is added ahead of the directives
+    /// here, in the order of the template, so the block's own directives still come last.
+    ///
+    /// * `template` - The configuration holding the defaults.
+    pub fn apply_template(&mut self, template: &Configuration) {
+        let inherited: Vec<ConfigItem> = template.directives.iter()
+            .filter(|directive| {
+                let key = Self::directive_key(directive);
+                !self.directives.iter().any(|existing| Self::directive_key(existing) == key)
+            })
+            .cloned()
+            .collect();
+        self.directives.splice(0..0, inherited);
+    }
+
+    /// The key a directive is matched on when merging: its name, or its raw text if it has
+    /// no name.
+    ///
+    /// * `directive` - The directive to match.
+    fn directive_key(directive: &ConfigItem) -> &str {
+        if directive.name.is_empty() {
+            directive.raw.trim()
+        } else {
+            &directive.name
+        }
+    }
+
+    /// Expand the variables in the string values of the block and its nested blocks.  Both
+    /// `$name` and `${name}` are replaced with the name of the block, and any other variable is
+    /// replaced with the environment variable of that name.  A variable without a value is left
+    /// as it was, or is reported as `ParseError::UndefinedVariable` if `strict` is set.
+    ///
+    /// * `strict` - True if a variable without a value is an error.
+    pub fn interpolate(&mut self, strict: bool) -> Result<(), ParseError> {
+        self.interpolate_with(&HashMap::new(), strict)
+    }
+
+    /// Expand the variables as `interpolate` does, looking up any variable other than `name`
+    /// in `variables` before the environment, as for the variables defined at the top level of
+    /// a jail.conf file.
+    ///
+    /// * `variables` - The values of the defined variables, keyed by name without the '$'.
+    /// * `strict` - True if a variable without a value is an error.
+    pub fn interpolate_with(&mut self, variables: &HashMap<String, String>, strict: bool) -> Result<(), ParseError> {
+        for directive in &mut self.directives {
+            for value in directive.values.values_mut() {
+                match value {
+                    ConfigValue::String(text) => *text = Self::expand(text, &self.name, variables, strict)?,
+                    ConfigValue::Array(texts) => {
+                        for text in texts.iter_mut() {
+                            *text = Self::expand(text, &self.name, variables, strict)?;
+                        }
+                    },
+                    _ => {},
+                }
+            }
+        }
+
+        for child in &mut self.children {
+            child.interpolate_with(variables, strict)?;
+        }
+        Ok(())
+    }
+
+    /// Replace the `$variable` and `${variable}` references in a value.  A '$' that is not
+    /// followed by a variable name, or a '${' without a closing '}', is kept as it is.
+    ///
+    /// * `text` - The value to expand.
+    /// * `name` - The name of the block, the value of `$name`.
+    /// * `variables` - The values of the defined variables.
+    /// * `strict` - True if a variable without a value is an error.
+    fn expand(text: &str, name: &str, variables: &HashMap<String, String>, strict: bool) -> Result<String, ParseError> {
+        let mut out = String::new();
+        let mut rest = text;
+
+        while let Some(start) = rest.find('$') {
+            out.push_str(&rest[..start]);
+            let after = &rest[start + 1..];
+            let (variable, length) = match after.strip_prefix('{') {
+                Some(braced) => match braced.find('}') {
+                    Some(end) => (&braced[..end], end + 2),
+                    None => ("", 0),
+                },
+                None => {
+                    let end = after.find(|token: char| !(token.is_ascii_alphanumeric() || token == '_'))
+                        .unwrap_or(after.len());
+                    (&after[..end], end)
+                },
+            };
+
+            let reference = &rest[start..start + 1 + length];
+            rest = &after[length..];
+            if variable.is_empty() {
+                out.push_str(reference);
+                continue;
+            }
+
+            match Self::variable(variable, name, variables) {
+                Some(value) => out.push_str(&value),
+                None if strict => return Err(ParseError::UndefinedVariable(variable.to_string())),
+                None => out.push_str(reference),
+            }
+        }
+
+        out.push_str(rest);
+        Ok(out)
+    }
+
+    /// Look up the value of a variable: the block name for `name`, then the defined variables,
+    /// and then the environment.
+    ///
+    /// * `variable` - The variable name.
+    /// * `name` - The name of the block.
+    /// * `variables` - The values of the defined variables.
+    fn variable(variable: &str, name: &str, variables: &HashMap<String, String>) -> Option<String> {
+        if variable == "name" {
+            Some(name.to_string())
+        } else {
+            variables.get(variable).cloned().or_else(|| std::env::var(variable).ok())
+        }
+    }
+
+    /// Render the configuration as jail.conf text.  The block is written as `name { ... }`
+    /// with one directive per line, indented by a tab for each level of nesting.  Directives
+    /// are written from their values so that changes to the values are reflected in the text,
+    /// and string values are quoted when they contain whitespace or characters that have a
+    /// meaning to the parser.  Parsing the result yields an equivalent configuration.
+    pub fn to_config_string(&self) -> String {
+        let mut out = String::new();
+        self.write_block(&mut out, 0);
+        out
+    }
+
+    /// Write the block, its directives and its nested blocks.
+    ///
+    /// * `out` - The text written so far.
+    /// * `depth` - The nesting level of the block.
+    fn write_block(&self, out: &mut String, depth: usize) {
+        let indent = "\t".repeat(depth);
+        if self.name.is_empty() {
+            out.push_str(&format!("{}{{\n", indent));
+        } else {
+            out.push_str(&format!("{}{} {{\n", indent, self.name));
+        }
+
+        for directive in &self.directives {
+            for line in Self::directive_lines(directive) {
+                out.push_str(&format!("{}\t{};\n", indent, line));
+            }
+        }
+
+        for child in &self.children {
+            child.write_block(out, depth + 1);
+        }
+
+        out.push_str(&format!("{}}}\n", indent));
+    }
+
+    /// Render a directive as one line per value, without the closing semicolon.  A directive
+    /// without values is written as it was read.
+    ///
+    /// * `directive` - The directive to render.
+    fn directive_lines(directive: &ConfigItem) -> Vec<String> {
+        if directive.values.is_empty() {
+            return vec![directive.raw.trim().to_string()];
+        }
+
+        directive.ordered_values().map(|(key, value)| match value {
+            ConfigValue::Boolean(true) => key.to_string(),
+            ConfigValue::Boolean(false) => format!("{} = false", key),
+            ConfigValue::String(value) => format!("{} = {}", key, Self::quote(value)),
+            ConfigValue::Integer(value) => format!("{} = {}", key, value),
+            ConfigValue::Array(values) => {
+                let values: Vec<String> = values.iter().map(|value| Self::quote(value)).collect();
+                format!("{} = {}", key, values.join(", "))
+            },
+        }).collect()
+    }
+
+    /// Quote a value if it is empty or contains whitespace or parser punctuation.  A quote or
+    /// backslash inside the value is escaped with a backslash, as jail(8) expects.
+    ///
+    /// * `value` - The value to quote.
+    fn quote(value: &str) -> String {
+        let needs_quotes = value.is_empty() || value.chars().any(|token| {
+            token.is_whitespace() || "\";#{},=\\".contains(token)
+        });
+
+        if needs_quotes {
+            format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+        } else {
+            value.to_string()
+        }
+    }
+}
+
+#[cfg(feature = "grpc")]
+impl From<&Container> for Configuration {
+    fn from(container: &Container) -> Self {
+        Configuration::from_container(container)
+    }
+}
+
+/// Describe the configuration as a container for the gRPC service.  The dataset is taken from
+/// `path`, or is `zpool/datasets/containers/<name>` if there is none, and the addresses are
+/// taken from `ip4.addr`, or are `<name>.local` if there are none.  The container is reported
+/// as not running, with no id, as those come from the running jails.
+#[cfg(feature = "grpc")]
+impl From<&Configuration> for Container {
+    fn from(configuration: &Configuration) -> Self {
+        let dataset = configuration.get_string("path")
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("zpool/datasets/containers/{}", configuration.name));
+        let addresses = configuration.get_list("ip4.addr")
+            .unwrap_or_else(|| vec![format!("{}.local", configuration.name)]);
+
+        Container {
+            name: configuration.name.clone(),
+            id: None,
+            dataset,
+            addresses,
+            running: false,
+        }
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::parser::config_parser::ConfigParser;
+    use crate::parser::parse_error::ParseError;
 
     #[test]
     fn test_set_name() {
@@ -61,6 +588,57 @@ mod test {
         assert_eq!(config.name, "test");
     }
 
+    #[test]
+    fn test_validate_name() {
+        assert_eq!(Configuration::builder().name("legolas").build().validate_name(), Ok(()));
+        assert_eq!(Configuration::builder().name("web-01_a").build().validate_name(), Ok(()));
+        assert_eq!(Configuration::default().validate_name(), Err(NameError::Empty));
+        assert_eq!(Configuration::builder().name("jails/web").build().validate_name(), Err(NameError::InvalidChar('/')));
+        assert_eq!(Configuration::builder().name("web.local").build().validate_name(), Err(NameError::InvalidChar('.')));
+        assert_eq!(Configuration::builder().name("*").build().validate_name(), Ok(()));
+        assert_eq!(Configuration::builder().name("*web").build().validate_name(), Err(NameError::InvalidChar('*')));
+        assert_eq!(Configuration::builder().name("web{persist;}x").build().validate_name(), Err(NameError::InvalidChar('{')));
+        for token in ['}', ';', '"', '=', '#', ' '] {
+            let name = format!("web{}x", token);
+            assert_eq!(Configuration::builder().name(&name).build().validate_name(), Err(NameError::InvalidChar(token)));
+        }
+        assert_eq!(Configuration::builder().name("-web").build().validate_name(), Err(NameError::InvalidChar('-')));
+    }
+
+    #[test]
+    fn test_validated_name_round_trip() -> Result<(), ParseError> {
+        for name in ["legolas", "web-01", "web-01_a", "élan", "*"] {
+            let config = Configuration::builder().name(name).directive("persist").build();
+            assert_eq!(config.validate_name(), Ok(()));
+
+            let parsed = ConfigParser::new().parse_content(&config.to_config_string())?;
+            assert_eq!(parsed.name, name);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_template() -> Result<(), ParseError> {
+        let mut configurations = ConfigParser::new().parse_all_content(r#"* {
+            persist;
+            path = "/jails/$name";
+        }
+        web {
+            path = "/usr/local/jails/web";
+        }"#)?;
+
+        assert!(configurations[0].is_template());
+        let template = configurations.remove(0);
+        let web = &mut configurations[0];
+        web.apply_template(&template);
+
+        assert_eq!(web.len(), 2);
+        assert_eq!(web.get_value("persist").as_deref(), Some(&ConfigValue::Boolean(true)));
+        assert_eq!(web.get_string("path"), Some("/usr/local/jails/web"));
+
+        Ok(())
+    }
+
     #[test]
     fn test_add_directive() {
         let mut config = Configuration::default();
@@ -69,4 +647,256 @@ mod test {
         assert_eq!(1, config.directives.len());
         assert_eq!(ConfigItem::new("foo"), config.directives[0]);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_add_child() {
+        let mut config = Configuration::default();
+        let mut child = Configuration::default();
+        child.set_name("inner");
+        config.add_child(child);
+        assert_eq!(1, config.children.len());
+        assert_eq!("inner", config.children[0].name);
+    }
+
+    #[test]
+    fn test_to_config_string() {
+        let mut config = Configuration::default();
+        config.set_name("fordo");
+        let mut bobo = ConfigItem::new("bobo");
+        bobo.add_value("bobo", ConfigValue::Boolean(true));
+        config.add_directive(&bobo);
+        let mut start = ConfigItem::new("exec.start = \"/bin/sh /etc/rc\"");
+        start.add_value("exec.start", ConfigValue::String("/bin/sh /etc/rc".to_string()));
+        config.add_directive(&start);
+
+        assert_eq!(config.to_config_string(), "fordo {\n\tbobo;\n\texec.start = \"/bin/sh /etc/rc\";\n}\n");
+    }
+
+    #[test]
+    fn test_builder() -> Result<(), ParseError> {
+        let config = Configuration::builder()
+            .name("web")
+            .directive("persist")
+            .value("ip4.addr", "10.0.0.1")
+            .value("exec.start", "/bin/sh /etc/rc")
+            .build();
+
+        assert_eq!("web", config.name);
+        assert_eq!(3, config.directives.len());
+        assert_eq!(Some(true), config.directives[0].get_bool("persist"));
+        assert_eq!(Some("10.0.0.1"), config.directives[1].get_string("ip4.addr"));
+
+        let parsed = ConfigParser::new().parse_content(&config.to_config_string())?;
+        assert_eq!(parsed.name, config.name);
+        assert_eq!(parsed.directives.len(), config.directives.len());
+        for (left, right) in parsed.directives.iter().zip(config.directives.iter()) {
+            assert_eq!(left.values, right.values);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge() -> Result<(), ParseError> {
+        let mut config = ConfigParser::new().parse_content(r#"{
+            persist;
+            path = "/jails/default";
+            ip4.addr = 10.0.0.1, 10.0.0.2;
+        }"#)?;
+        let overrides = Configuration::builder()
+            .name("web")
+            .value("path", "/jails/web")
+            .value("host.hostname", "web.local")
+            .list("ip4.addr", &["10.0.0.3".to_string()])
+            .build();
+
+        config.merge(&overrides);
+        assert_eq!(config.name, "web");
+        assert_eq!(config.directives.len(), 4);
+        assert_eq!(config.directives[0].name, "persist");
+        assert_eq!(config.get_string("path"), Some("/jails/web"));
+        assert_eq!(config.directives[1].name, "path");
+        assert_eq!(config.get_list("ip4.addr"), Some(vec!["10.0.0.3".to_string()]));
+        assert_eq!(config.directives[3].name, "host.hostname");
+
+        config.merge(&Configuration::builder().value("path", "/jails/other").build());
+        assert_eq!(config.name, "web");
+        assert_eq!(config.get_string("path"), Some("/jails/other"));
+        assert_eq!(config.directives.len(), 4);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_interpolate() -> Result<(), ParseError> {
+        let mut config = ConfigParser::new().parse_content(r#"web {
+            path = "/jails/$name";
+            host.hostname = "${name}.local";
+            exec.start = "echo $ ${ $1 ${UNDEFINED_CONMAN_VARIABLE}";
+            env = "${PATH}";
+        }"#)?;
+
+        config.interpolate(false)?;
+        assert_eq!(config.get_string("path"), Some("/jails/web"));
+        assert_eq!(config.get_string("host.hostname"), Some("web.local"));
+        assert_eq!(config.get_string("exec.start"), Some("echo $ ${ $1 ${UNDEFINED_CONMAN_VARIABLE}"));
+        assert_eq!(config.get_string("env"), std::env::var("PATH").ok().as_deref());
+
+        let mut strict = Configuration::builder().name("web").value("path", "/jails/$UNDEFINED_CONMAN_VARIABLE").build();
+        assert!(matches!(strict.interpolate(true), Err(ParseError::UndefinedVariable(name)) if name == "UNDEFINED_CONMAN_VARIABLE"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_equality() -> Result<(), ParseError> {
+        let build = || Configuration::builder()
+            .name("web")
+            .directive("persist")
+            .value("ip4.addr", "10.0.0.1")
+            .build();
+
+        assert_eq!(build(), build());
+        assert_ne!(build(), Configuration::builder().name("web").directive("persist").build());
+        assert_ne!(build(), Configuration::builder().name("db").directive("persist").value("ip4.addr", "10.0.0.1").build());
+
+        let content = "web {\n\tpersist;\n\tip4.addr = 10.0.0.1;\n}\n";
+        assert_eq!(ConfigParser::new().parse_content(content)?, ConfigParser::new().parse_content(content)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_len_and_find_directive() -> Result<(), ParseError> {
+        let config = ConfigParser::new().parse_content(r#"fordo {
+            bobo;
+            coco="dodo";
+        }"#)?;
+
+        assert_eq!(config.len(), 2);
+        assert!(!config.is_empty());
+        assert!(Configuration::default().is_empty());
+        assert_eq!(config.get_directive(1).and_then(|directive| directive.get_string("coco")), Some("dodo"));
+        assert_eq!(config.get_directive(2), None);
+        assert_eq!(config.find_directive("bobo").and_then(|directive| directive.get_bool("bobo")), Some(true));
+        assert_eq!(config.find_directive("momo"), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_value() {
+        let config = Configuration::builder()
+            .name("web")
+            .directive("persist")
+            .value("host.hostname", "old")
+            .value("host.hostname", "new")
+            .build();
+
+        assert_eq!(config.get_value("persist").as_deref(), Some(&ConfigValue::Boolean(true)));
+        assert_eq!(config.get_string("host.hostname"), Some("new"));
+        assert_eq!(config.get_string("persist"), None);
+        assert_eq!(config.get_value("path"), None);
+    }
+
+    #[test]
+    fn test_get_appended_value() -> Result<(), ParseError> {
+        let config = ConfigParser::new().parse_content(r#"web {
+            ip4.addr = 1.1.1.1;
+            ip4.addr += 2.2.2.2, 3.3.3.3;
+            ip6.addr += "2001:db8::1";
+            path = "/jails/web";
+        }"#)?;
+
+        let addresses = vec!["1.1.1.1".to_string(), "2.2.2.2".to_string(), "3.3.3.3".to_string()];
+        assert_eq!(config.get_list("ip4.addr"), Some(addresses.clone()));
+        assert_eq!(config.get_value("ip4.addr").as_deref(), Some(&ConfigValue::Array(addresses)));
+        assert_eq!(config.get_string("ip4.addr"), None);
+        assert_eq!(config.get_list("ip6.addr"), Some(vec!["2001:db8::1".to_string()]));
+        assert_eq!(config.get_string("path"), Some("/jails/web"));
+
+        Ok(())
+    }
+
+    #[cfg(feature = "grpc")]
+    #[test]
+    fn test_container_from_configuration() -> Result<(), ParseError> {
+        let config = ConfigParser::new().parse_content(r#"web {
+            path = "/jails/web";
+            ip4.addr = 10.0.0.1, 10.0.0.2;
+            persist;
+        }"#)?;
+
+        let container = Container::from(&config);
+        assert_eq!(container.name, "web");
+        assert_eq!(container.dataset, "/jails/web");
+        assert_eq!(container.addresses, vec!["10.0.0.1".to_string(), "10.0.0.2".to_string()]);
+        assert_eq!(container.id, None);
+        assert!(!container.running);
+
+        let container = Container::from(&Configuration::builder().name("db").directive("persist").build());
+        assert_eq!(container.dataset, "zpool/datasets/containers/db");
+        assert_eq!(container.addresses, vec!["db.local".to_string()]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_iter_values() {
+        let mut first = ConfigItem::new("first");
+        first.add_value("path", ConfigValue::String("/jails/web".to_string()));
+        first.add_value("persist", ConfigValue::Boolean(true));
+        let mut second = ConfigItem::new("second");
+        second.add_value("ip4.addr", ConfigValue::Array(vec!["10.0.0.1".to_string()]));
+        second.add_value("devfs_ruleset", ConfigValue::Integer(4));
+
+        let mut config = Configuration::default();
+        config.add_directive(&first);
+        config.add_directive(&second);
+
+        let values: Vec<(&str, &ConfigValue)> = config.iter_values().collect();
+        assert_eq!(values, vec![
+            ("path", &ConfigValue::String("/jails/web".to_string())),
+            ("persist", &ConfigValue::Boolean(true)),
+            ("ip4.addr", &ConfigValue::Array(vec!["10.0.0.1".to_string()])),
+            ("devfs_ruleset", &ConfigValue::Integer(4)),
+        ]);
+    }
+
+    #[test]
+    fn test_round_trip() -> Result<(), ParseError> {
+        let original = ConfigParser::new().parse_content(r#"fordo {
+            bobo;
+            coco="dodo";
+            inner {
+                ip4.addr = 10.0.0.1, 10.0.0.2;
+            }
+        }"#)?;
+
+        let round_trip = ConfigParser::new().parse_content(&original.to_config_string())?;
+
+        assert_eq!(round_trip.name, original.name);
+        assert_eq!(round_trip.directives.len(), original.directives.len());
+        for (left, right) in round_trip.directives.iter().zip(original.directives.iter()) {
+            assert_eq!(left.values, right.values);
+        }
+        assert_eq!(round_trip.children[0].name, original.children[0].name);
+        assert_eq!(round_trip.children[0].directives[0].values, original.children[0].directives[0].values);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_round_trip_escapes() -> Result<(), ParseError> {
+        let value = r#"say "hi" from C:\jails\web"#;
+        let original = Configuration::builder().name("web").value("exec.start", value).build();
+
+        let text = original.to_config_string();
+        assert!(text.contains(r#"exec.start = "say \"hi\" from C:\\jails\\web";"#), "{}", text);
+        let round_trip = ConfigParser::new().parse_content(&text)?;
+        assert_eq!(round_trip.len(), 1);
+        assert_eq!(round_trip.get_string("exec.start"), Some(value));
+
+        Ok(())
+    }
+}
@@ -22,13 +22,44 @@
 //! SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 //!
 
-/// Represents a configuration item, or a declaration inside the container definition.
+use std::collections::HashMap;
+use crate::parser::parse_error::ParseError;
+
+/// The value assigned by a configuration directive.
+///
+/// * String - A single value, with any surrounding quotes removed.
+/// * Boolean - A bare directive with no assigned value, such as `persist`.
+/// * Array - A comma separated list of values.
+/// * Integer - A numeric value, such as `devfs_ruleset = 5`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigValue {
+    String(String),
+    Boolean(bool),
+    Array(Vec<String>),
+    Integer(i64),
+}
+
+/// Represents a configuration item, or a declaration inside the container definition.  The
+/// line parser in `config_parser` uses an item for a whole container, with the container name
+/// and any bare directives.
 ///
-/// * `raw` - The raw text encountered during tokenization
+/// * `name` - The container name, when the item represents a whole container.
+/// * `raw` - The raw text encountered during tokenization, exactly as it was typed.  See
+///   `normalized` for a form that is easier to compare.
+/// * `span` - The line and column where the item began, starting at 1.  An item that was not
+///   read from a configuration has a span of `(0, 0)`.
+/// * `values` - The values assigned by the item, keyed by the directive name.
+/// * `order` - The keys of `values` in the order they were first assigned.
+/// * `directives` - Bare directives, such as `mount.devfs;`, read by the line parser.
 ///
 #[derive(Debug, Clone, PartialEq)]
 pub struct ConfigItem {
+    pub name: String,
     pub raw: String,
+    pub span: (usize, usize),
+    pub values: HashMap<String, ConfigValue>,
+    pub order: Vec<String>,
+    pub directives: Vec<String>,
 }
 
 impl ConfigItem {
@@ -36,9 +67,132 @@ impl ConfigItem {
     /// Creates a new ConfigItem
     ///
     /// * `raw` - The raw string for the item
-    pub fn new(raw: &str) -> Self {
+    pub fn new(raw: impl Into<String>) -> Self {
         Self {
-            raw: raw.to_string(),
+            name: String::new(),
+            raw: raw.into(),
+            span: (0, 0),
+            values: HashMap::new(),
+            order: Vec::new(),
+            directives: Vec::new(),
+        }
+    }
+
+    /// Creates a new ConfigItem for a whole container.
+    ///
+    /// * `name` - The container name.
+    pub fn with_name(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            ..Self::new("")
+        }
+    }
+
+    /// Assigns a value to a key, replacing any previous value for that key.  A replaced value
+    /// keeps the position of the key in `ordered_values`.
+    ///
+    /// * `key` - The directive name.
+    /// * `value` - The value assigned to the directive.
+    pub fn add_value(&mut self, key: impl Into<String>, value: ConfigValue) {
+        let key = key.into();
+        if !self.order.contains(&key) {
+            self.order.push(key.clone());
+        }
+        self.values.insert(key, value);
+    }
+
+    /// Assigns a value to a key that has not been assigned yet.  Returns
+    /// `ParseError::DuplicateKey` and leaves the existing value in place if the key already
+    /// has a value.
+    ///
+    /// * `key` - The directive name.
+    /// * `value` - The value assigned to the directive.
+    pub fn add_value_checked(&mut self, key: impl Into<String>, value: ConfigValue) -> Result<(), ParseError> {
+        let key = key.into();
+        if self.values.contains_key(&key) {
+            return Err(ParseError::DuplicateKey { block: self.name.clone(), key });
+        }
+        self.add_value(key, value);
+        Ok(())
+    }
+
+    /// The values assigned by the item, in the order their keys were first assigned.
+    pub fn ordered_values(&self) -> impl Iterator<Item = (&str, &ConfigValue)> {
+        self.order.iter().filter_map(|key| self.values.get(key).map(|value| (key.as_str(), value)))
+    }
+
+    /// The raw text in a standard form for comparing and splitting.  Leading and trailing
+    /// whitespace is removed, each run of whitespace becomes a single space, and an '=' (or
+    /// `+=`) has exactly one space on either side, so `coco   =   "dodo"` and `coco="dodo"`
+    /// both become `coco = "dodo"`.  Text inside double quotes is left as it is.
+    pub fn normalized(&self) -> String {
+        let mut normalized = String::with_capacity(self.raw.len());
+        let mut quoted = false;
+        let mut escaped = false;
+        let mut pending_space = false;
+
+        for token in self.raw.trim().chars() {
+            if quoted {
+                normalized.push(token);
+                quoted = escaped || token != '"';
+                escaped = !escaped && token == '\\';
+            } else if token.is_whitespace() {
+                pending_space = true;
+            } else if token == '=' {
+                let plus = normalized.ends_with('+');
+                if plus {
+                    normalized.pop();
+                }
+                normalized.truncate(normalized.trim_end().len());
+                normalized.push_str(if plus { " += " } else { " = " });
+                pending_space = false;
+            } else {
+                if pending_space && !normalized.ends_with(' ') {
+                    normalized.push(' ');
+                }
+                pending_space = false;
+                normalized.push(token);
+                quoted = token == '"';
+            }
+        }
+        normalized.truncate(normalized.trim_end().len());
+        normalized
+    }
+
+    /// Records a bare directive.
+    ///
+    /// * `directive` - The directive text.
+    pub fn add_directive(&mut self, directive: impl Into<String>) {
+        self.directives.push(directive.into());
+    }
+
+    /// Gets a string value.  Returns `None` if the key is absent or the value is not a string.
+    ///
+    /// * `key` - The directive name.
+    pub fn get_string(&self, key: &str) -> Option<&str> {
+        match self.values.get(key) {
+            Some(ConfigValue::String(value)) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Gets a boolean value.  Returns `None` if the key is absent or the value is not a boolean.
+    ///
+    /// * `key` - The directive name.
+    pub fn get_bool(&self, key: &str) -> Option<bool> {
+        match self.values.get(key) {
+            Some(ConfigValue::Boolean(value)) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Gets a list of values.  Returns `None` if the key is absent or the value is not a list.
+    ///
+    /// * `key` - The directive name.
+    pub fn get_array(&self, key: &str) -> Option<&[String]> {
+        match self.values.get(key) {
+            Some(ConfigValue::Array(values)) => Some(values),
+            _ => None,
         }
     }
 }
@@ -51,5 +205,88 @@ mod test {
     fn new_test() {
         let item = ConfigItem::new("foo");
         assert_eq!("foo".to_string(), item.raw);
+        assert_eq!((0, 0), item.span);
+
+        let item = ConfigItem::new(String::from("bar"));
+        assert_eq!("bar".to_string(), item.raw);
+    }
+
+    #[test]
+    fn with_name_test() {
+        let mut item = ConfigItem::with_name("legolas".to_string());
+        item.add_directive("mount.devfs;");
+        assert_eq!("legolas".to_string(), item.name);
+        assert_eq!("".to_string(), item.raw);
+        assert_eq!(vec!["mount.devfs;".to_string()], item.directives);
+    }
+
+    #[test]
+    fn add_value_test() {
+        let mut item = ConfigItem::new("coco=\"dodo\"");
+        item.add_value("coco", ConfigValue::String("dodo".to_string()));
+        assert_eq!(Some(&ConfigValue::String("dodo".to_string())), item.values.get("coco"));
+
+        item.add_value("coco", ConfigValue::Boolean(true));
+        assert_eq!(1, item.values.len());
+        assert_eq!(Some(&ConfigValue::Boolean(true)), item.values.get("coco"));
+    }
+
+    #[test]
+    fn ordered_values_test() {
+        let mut item = ConfigItem::with_name("legolas");
+        item.add_value("path", ConfigValue::String("/jails/legolas".to_string()));
+        item.add_value("host.hostname", ConfigValue::String("legolas.local".to_string()));
+        item.add_value("allow.raw_sockets", ConfigValue::Boolean(true));
+        item.add_value("path", ConfigValue::String("/jails/elf".to_string()));
+
+        let keys: Vec<&str> = item.ordered_values().map(|(key, _)| key).collect();
+        assert_eq!(vec!["path", "host.hostname", "allow.raw_sockets"], keys);
+        assert_eq!(Some(("path", &ConfigValue::String("/jails/elf".to_string()))), item.ordered_values().next());
+
+        item.values.remove("host.hostname");
+        let keys: Vec<&str> = item.ordered_values().map(|(key, _)| key).collect();
+        assert_eq!(vec!["path", "allow.raw_sockets"], keys);
+    }
+
+    #[test]
+    fn add_value_checked_test() {
+        let mut item = ConfigItem::with_name("legolas");
+        assert!(item.add_value_checked("host", ConfigValue::String("new".to_string())).is_ok());
+
+        let result = item.add_value_checked("host", ConfigValue::String("inherit".to_string()));
+        assert!(matches!(result, Err(ParseError::DuplicateKey { ref block, ref key }) if block == "legolas" && key == "host"));
+        assert_eq!(Some("new"), item.get_string("host"));
     }
-}
\ No newline at end of file
+
+    fn populated_item() -> ConfigItem {
+        let mut item = ConfigItem::new("jail");
+        item.add_value("host.hostname", ConfigValue::String("jail.local".to_string()));
+        item.add_value("persist", ConfigValue::Boolean(true));
+        item.add_value("ip4.addr", ConfigValue::Array(vec!["10.0.0.1".to_string(), "10.0.0.2".to_string()]));
+        item
+    }
+
+    #[test]
+    fn get_string_test() {
+        let item = populated_item();
+        assert_eq!(Some("jail.local"), item.get_string("host.hostname"));
+        assert_eq!(None, item.get_string("persist"));
+        assert_eq!(None, item.get_string("path"));
+    }
+
+    #[test]
+    fn get_bool_test() {
+        let item = populated_item();
+        assert_eq!(Some(true), item.get_bool("persist"));
+        assert_eq!(None, item.get_bool("host.hostname"));
+        assert_eq!(None, item.get_bool("path"));
+    }
+
+    #[test]
+    fn get_array_test() {
+        let item = populated_item();
+        assert_eq!(Some(&["10.0.0.1".to_string(), "10.0.0.2".to_string()][..]), item.get_array("ip4.addr"));
+        assert_eq!(None, item.get_array("persist"));
+        assert_eq!(None, item.get_array("path"));
+    }
+}
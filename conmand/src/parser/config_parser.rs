@@ -22,19 +22,83 @@
 //! SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 //!
 
-use std::{fs, path::Path};
-use crate::parser::config_item::ConfigItem;
-use crate::parser::parser_state::ParserState;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+use std::{fs, path::{Path, PathBuf}};
+use log::warn;
+use crate::parser::config_item::{ConfigItem, ConfigValue};
+use crate::parser::parser_state::{is_directive_name_char, ParserState};
 use crate::parser::config::Configuration;
+use crate::parser::jail_conf::JailConf;
+use crate::parser::parse_error::ParseError;
 
 /// The parsing state when breaking apart a container configuration.  The state tracked is
 /// a stack of states.  States are pushed and popped off the stack, with the top-most state
 /// being the 'current' state.
 ///
 /// * `state_stack` - A stack of the structure.
+/// * `offset` - The byte offset of the character being processed.
+/// * `line` - The line number of the character being processed, starting at 1.
+/// * `column` - The column number of the character being processed, starting at 1.
+/// * `after_cr` - True if the last character processed was a carriage return.
+/// * `config` - The configuration built up by `feed` so far.
+/// * `lenient` - True if a bad directive inside a block is skipped rather than fatal.
+/// * `skipping` - True while skipping the rest of a bad directive.
+/// * `skip_quoted` - True while skipping a quoted string inside a bad directive.
+/// * `escaped` - True if the last character was a backslash inside a quoted string.
+/// * `errors` - The errors skipped over in lenient mode.
 #[derive(Debug, Default)]
 pub struct ConfigParser {
     pub state_stack: Vec<ParserState>,
+    offset: usize,
+    line: usize,
+    column: usize,
+    after_cr: bool,
+    config: Configuration,
+    lenient: bool,
+    skipping: bool,
+    skip_quoted: bool,
+    escaped: bool,
+    errors: Vec<ParseError>,
+}
+
+/// The result of a lenient parse: the configuration that could be read, and the errors for
+/// each directive that was skipped.
+///
+/// * `configuration` - The configuration without the skipped directives.
+/// * `errors` - One error for each skipped directive, in the order they were found.
+#[derive(Debug, Default)]
+pub struct LenientParse {
+    pub configuration: Configuration,
+    pub errors: Vec<ParseError>,
+}
+
+/// Measurements taken while parsing a container configuration.
+///
+/// * `bytes` - The length of the content in bytes.
+/// * `directives` - The number of directives read, including those in nested blocks.
+/// * `comments` - The number of comments read, including those in nested blocks.
+/// * `elapsed` - How long the parse took.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ParseStats {
+    pub bytes: usize,
+    pub directives: usize,
+    pub comments: usize,
+    pub elapsed: Duration,
+}
+
+impl ParseStats {
+
+    /// Count the directives and comments in a configuration and its nested blocks.
+    ///
+    /// * `config` - The configuration to count.
+    fn count(&mut self, config: &Configuration) {
+        self.directives += config.directives.len();
+        self.comments += config.comments.len();
+        for child in &config.children {
+            self.count(child);
+        }
+    }
 }
 
 impl ConfigParser {
@@ -43,6 +107,16 @@ impl ConfigParser {
     pub fn new() -> Self {
         ConfigParser {
             state_stack: vec![ParserState::Starting],
+            offset: 0,
+            line: 1,
+            column: 1,
+            after_cr: false,
+            config: Configuration::default(),
+            lenient: false,
+            skipping: false,
+            skip_quoted: false,
+            escaped: false,
+            errors: Vec::new(),
         }
     }
 
@@ -52,48 +126,570 @@ impl ConfigParser {
     pub fn parse_file<P: AsRef<Path>>(
         &mut self,
         path: P,
-    ) -> Result<Configuration, Box<dyn std::error::Error>> {
+    ) -> Result<Configuration, ParseError> {
         let content = fs::read_to_string(path)?;
         self.parse_content(&content)
     }
 
-    /// Tokenize the content from a container configuration.  Process each character, one at a
-    /// time, and use that to determine the next state as per the state transitions.  If there
-    /// is a state change, dispatch into the state change handling functions. Returns the parsed
-    /// configuration or an error.
+    /// Read every container configuration from a file.
     ///
-    /// If we are reading a name, and there is no state transition, we preserve the read token
-    /// as part of the name.  If we are in the `ParserState::InDirective` state, we save the
-    /// token as part of the directive string.
+    /// * `p` - The path to the file
+    pub fn parse_all_file<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+    ) -> Result<Vec<Configuration>, ParseError> {
+        let content = fs::read_to_string(path)?;
+        self.parse_all_content(&content)
+    }
+
+    /// Read every container configuration from a file, following the top-level `include` and
+    /// `.include` directives in it.  An include names a file or a glob pattern, relative to
+    /// the directory of the file that includes it, and the blocks of each matching file are
+    /// added after the blocks of the including file.  An anonymous block is named after the
+    /// file it is in, so a fragment `web.conf` holding `{ persist; }` defines `web`.  A file
+    /// that has already been read is skipped, so include cycles end rather than recursing
+    /// forever.
+    ///
+    /// * `path` - The path to the file
+    pub fn parse_all_file_with_includes<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+    ) -> Result<Vec<Configuration>, ParseError> {
+        self.parse_all_file_with_variables(path).map(|jail_conf| jail_conf.configurations)
+    }
+
+    /// Read every container configuration from a file, following includes as
+    /// `parse_all_file_with_includes` does, along with the variables defined at the top level
+    /// of the file and the files it includes.  A variable defined again in a later file
+    /// replaces the earlier definition.  The parameters set at the top level of each file are
+    /// returned as a `*` template block, ahead of the blocks of that file.
+    ///
+    /// * `path` - The path to the file
+    pub fn parse_all_file_with_variables<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+    ) -> Result<JailConf, ParseError> {
+        let mut visited = HashSet::new();
+        let mut variables = HashMap::new();
+        let configurations = self.parse_included_file(path.as_ref(), &mut visited, &mut variables)?;
+        Ok(JailConf { variables, configurations })
+    }
+
+    /// Read the variables and blocks of one file and then of every file it includes.
+    ///
+    /// * `path` - The path to the file
+    /// * `visited` - The canonical paths of the files read so far.
+    /// * `variables` - The variables defined so far.
+    fn parse_included_file(
+        &mut self,
+        path: &Path,
+        visited: &mut HashSet<PathBuf>,
+        variables: &mut HashMap<String, String>,
+    ) -> Result<Vec<Configuration>, ParseError> {
+        let path = fs::canonicalize(path)?;
+        if !visited.insert(path.clone()) {
+            return Ok(vec![]);
+        }
+
+        let (includes, content) = Self::take_includes(&fs::read_to_string(&path)?);
+        let (defined, parameters, content) = Self::take_variables(&content);
+        variables.extend(defined);
+        let mut configs = self.parse_all_content(&content)?;
+        if let Some(defaults) = Self::parse_defaults(&parameters)? {
+            configs.insert(0, defaults);
+        }
+        let stem = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or_default();
+        for config in configs.iter_mut().filter(|config| config.is_anonymous()) {
+            config.set_name(stem);
+            config.validate_name()?;
+        }
+        let base = path.parent().unwrap_or(Path::new("/"));
+
+        for include in includes {
+            let pattern = base.join(&include);
+            let pattern = pattern.to_str().ok_or_else(|| ParseError::InvalidInclude(include.clone()))?;
+            let paths = glob::glob(pattern).map_err(|_| ParseError::InvalidInclude(include.clone()))?;
+            for included in paths {
+                let included = included.map_err(std::io::Error::from)?;
+                configs.extend(self.parse_included_file(&included, visited, variables)?);
+            }
+        }
+
+        Ok(configs)
+    }
+
+    /// Remove the top-level `include` and `.include` directives from the content, returning
+    /// the paths they name and the remaining content.  A directive is the word, whitespace,
+    /// and a quoted path ending in ';', such as `.include "jail.conf.d/*.conf";`, so a jail
+    /// named `include` is left alone, and a '#' inside the quoted path is part of the path.
+    /// Each directive is blanked out with spaces rather than removed, so that positions in
+    /// parse errors still match the file.
+    ///
+    /// * `content` - The content as a string
+    fn take_includes(content: &str) -> (Vec<String>, String) {
+        let mut includes = vec![];
+        let mut depth = 0usize;
+        let mut quoted = false;
+
+        let lines: Vec<String> = content.split_inclusive('\n').map(|line| {
+            let starts_quoted = quoted;
+            let (length, opens, closes) = Self::scan_line(line, &mut quoted);
+            let code = &line[..length];
+            let statement = code.trim();
+            let target = statement.strip_prefix(".include").or_else(|| statement.strip_prefix("include"))
+                .filter(|rest| rest.starts_with(char::is_whitespace))
+                .and_then(|rest| rest.trim().strip_suffix(';'))
+                .map(str::trim_end)
+                .filter(|path| path.len() >= 2 && path.starts_with('"') && path.ends_with('"'));
+
+            if depth == 0 && !starts_quoted && let Some(target) = target {
+                includes.push(Self::unquote(target));
+                let blank: String = code.chars().map(|token| if token == '\n' { token } else { ' ' }).collect();
+                return format!("{}{}", blank, &line[code.len()..]);
+            }
+
+            depth += opens;
+            depth = depth.saturating_sub(closes);
+            line.to_string()
+        }).collect();
+
+        (includes, lines.concat())
+    }
+
+    /// Remove the top-level statements, such as `$domain = "example.org";` or `persist;`, from
+    /// the content, returning the variables defined, the other parameters set and the remaining
+    /// content.  A statement is on a line of its own outside of any block.  An assignment to a
+    /// name starting with '$' defines a variable, and anything else is a parameter that applies
+    /// to every jail, which is returned as the statement text.  Each statement is blanked out
+    /// with spaces, as `take_includes` does, so positions in parse errors still match the
+    /// content.  A '#' or a brace inside a quoted value is part of the value.
+    ///
+    /// * `content` - The content as a string
+    fn take_variables(content: &str) -> (HashMap<String, String>, Vec<String>, String) {
+        let mut variables = HashMap::new();
+        let mut parameters = Vec::new();
+        let mut depth = 0usize;
+        let mut quoted = false;
+
+        let lines: Vec<String> = content.split_inclusive('\n').map(|line| {
+            let starts_quoted = quoted;
+            let (length, opens, closes) = Self::scan_line(line, &mut quoted);
+            let code = &line[..length];
+            let statement = code.trim();
+
+            if depth == 0 && !starts_quoted && opens == 0 && closes == 0
+                && let Some(body) = statement.strip_suffix(';') {
+                let (key, value) = body.split_once('=').unwrap_or((body, ""));
+                let is_name = |name: &str| !name.is_empty() && name.chars().all(is_directive_name_char);
+                let taken = match key.trim().strip_prefix('$') {
+                    Some(variable) if is_name(variable) && key.len() < body.len() => {
+                        variables.insert(variable.to_string(), Self::unquote(value.trim()));
+                        true
+                    },
+                    Some(_) => false,
+                    None if is_name(key.trim().trim_end_matches('+').trim_end()) => {
+                        parameters.push(statement.to_string());
+                        true
+                    },
+                    None => false,
+                };
+                if taken {
+                    let blank: String = code.chars().map(|token| if token == '\n' { token } else { ' ' }).collect();
+                    return format!("{}{}", blank, &line[code.len()..]);
+                }
+            }
+
+            depth += opens;
+            depth = depth.saturating_sub(closes);
+            line.to_string()
+        }).collect();
+
+        (variables, parameters, lines.concat())
+    }
+
+    /// Parse the parameters set at the top level of a file as a `*` template block, so they
+    /// apply to every jail as the `*` block's do.  Returns `None` if there are none.
+    ///
+    /// * `parameters` - The statements taken by `take_variables`.
+    fn parse_defaults(parameters: &[String]) -> Result<Option<Configuration>, ParseError> {
+        if parameters.is_empty() {
+            return Ok(None);
+        }
+        ConfigParser::new().parse_content(&format!("* {{\n{}\n}}\n", parameters.join("\n"))).map(Some)
+    }
+
+    /// Scan a line for the code before any comment and count the braces in it, skipping
+    /// anything inside a quoted string.  A backslash in a quoted string escapes the character
+    /// after it.  The quote state is carried from line to line, as a quoted string may span
+    /// lines.  Returns the length of the code in bytes and the number of '{' and '}' in it.
+    ///
+    /// * `line` - The line to scan.
+    /// * `quoted` - True if the line starts inside a quoted string; left as it is at the end.
+    fn scan_line(line: &str, quoted: &mut bool) -> (usize, usize, usize) {
+        let mut escaped = false;
+        let mut opens = 0;
+        let mut closes = 0;
+
+        for (index, token) in line.char_indices() {
+            if *quoted {
+                *quoted = escaped || token != '"';
+                escaped = !escaped && token == '\\';
+                continue;
+            }
+            match token {
+                '"' => *quoted = true,
+                '#' => return (index, opens, closes),
+                '{' => opens += 1,
+                '}' => closes += 1,
+                _ => {},
+            }
+        }
+        (line.len(), opens, closes)
+    }
+
+    /// Tokenize the content from a container configuration.  The content is passed to `feed`
+    /// in one piece and then checked as by `finish`.  Returns the parsed configuration or an
+    /// error.
+    ///
+    /// An illegal character is reported as `ParseError::UnexpectedChar`, and content that ends
+    /// before the configuration block is closed is reported as `ParseError::UnterminatedBlock`
+    /// (or `ParseError::UnterminatedComment` when it ends in a comment inside the block).  Content
+    /// with only whitespace and comments is reported as `ParseError::NoConfiguration`, while a
+    /// block with no directives, such as `empty { }`, is a configuration with no directives.  A
+    /// key assigned more than once in a block is reported as `ParseError::DuplicateKey`.
+    ///
+    /// The state stack and position are reset on entry, so a parser can be reused across files
+    /// even when a previous parse ended part way through a block.
     ///
     /// * `content` - The content as a string
     pub fn parse_content(
         &mut self,
         content: &str,
-    ) -> Result<Configuration, Box<dyn std::error::Error>> {
+    ) -> Result<Configuration, ParseError> {
+
+        self.parse_content_with_stats(content).map(|(config, _)| config)
+    }
+
+    /// Tokenize the container configuration as `parse_content` does, and also report the size
+    /// of the content, the number of directives and comments read, and how long it took.
+    ///
+    /// * `content` - The content as a string
+    pub fn parse_content_with_stats(
+        &mut self,
+        content: &str,
+    ) -> Result<(Configuration, ParseStats), ParseError> {
 
+        let started = Instant::now();
+        *self = ConfigParser::new();
+        self.feed(content)?;
+        let config = self.take_configuration()?;
+
+        let mut stats = ParseStats { bytes: content.len(), ..ParseStats::default() };
+        stats.count(&config);
+        stats.elapsed = started.elapsed();
+        Ok((config, stats))
+    }
+
+    /// Tokenize a container configuration, recovering from bad directives.  When a character
+    /// that is not legal is found inside a block, the error is recorded, the rest of the
+    /// directive up to the next ';' or '}' is skipped, and we carry on seeking the next
+    /// directive.  When the content ends part way through a directive, the partial directive is
+    /// dropped with a warning, `ParseError::TruncatedDirective` is recorded, and the open blocks
+    /// are closed.  Content that ends in a comment inside a block is handled the same way, with
+    /// the comment kept and `ParseError::UnterminatedComment` recorded.  Other errors in the
+    /// structure of the block, such as a bad container name, a block that ends between
+    /// directives without its closing brace, or content that ends in a container name before
+    /// any block is opened, still end the parse.
+    ///
+    /// * `content` - The content as a string
+    pub fn parse_content_lenient(
+        &mut self,
+        content: &str,
+    ) -> Result<LenientParse, ParseError> {
+
+        *self = ConfigParser { lenient: true, ..ConfigParser::new() };
+        self.feed(content)?;
+        self.recover_at_end_of_content();
+        let configuration = self.take_configuration()?;
+        Ok(LenientParse { configuration, errors: std::mem::take(&mut self.errors) })
+    }
+
+    /// Incrementally tokenize part of a container configuration, such as a chunk read from a
+    /// socket.  Each character advances the state machine and the result is accumulated in the
+    /// parser, so a configuration may be split across any number of calls.  Once all of the
+    /// content has been fed, call `finish` to get the configuration.
+    ///
+    /// * `chunk` - The next part of the content.
+    pub fn feed(&mut self, chunk: &str) -> Result<(), ParseError> {
+        let mut config = std::mem::take(&mut self.config);
+        let result = chunk.chars().try_for_each(|val| self.consume(&mut config, val));
+        self.config = config;
+        result
+    }
+
+    /// Finish an incremental parse.  Checks that the content fed so far ended outside of any
+    /// block and returns the accumulated configuration, or `ParseError::UnterminatedBlock`
+    /// (or `ParseError::UnterminatedComment`) if it did not.
+    pub fn finish(mut self) -> Result<Configuration, ParseError> {
+        self.take_configuration()
+    }
+
+    /// Check the terminal state and move the accumulated configuration out of the parser,
+    /// leaving the state stack in place.  Content that never closed a block is reported as
+    /// `ParseError::NoConfiguration` if it held nothing but whitespace and comments, or as
+    /// `ParseError::UnterminatedBlock` if it named a container without opening its block.  A
+    /// duplicate key is an error, or is recorded with the other errors in lenient mode.
+    fn take_configuration(&mut self) -> Result<Configuration, ParseError> {
+        self.end_of_content()?;
+        if !self.state_stack.contains(&ParserState::EndBlock) {
+            return Err(if self.config.name.is_empty() {
+                ParseError::NoConfiguration
+            } else {
+                ParseError::UnterminatedBlock
+            });
+        }
+        if !self.config.is_anonymous() {
+            self.config.validate_name()?;
+        }
+        if let Err(error) = self.config.check_duplicates() {
+            if !self.lenient {
+                return Err(error);
+            }
+            self.errors.push(error);
+        }
+        Ok(std::mem::take(&mut self.config))
+    }
+
+    /// Tokenize content that defines variables or sets parameters at the top level as well as
+    /// any number of container configurations.  The statements may come before or between the
+    /// blocks.  The variables are returned with the configurations for use in interpolation,
+    /// and the parameters as a `*` template block ahead of the other configurations.
+    ///
+    /// * `content` - The content as a string
+    pub fn parse_all_content_with_variables(
+        &mut self,
+        content: &str,
+    ) -> Result<JailConf, ParseError> {
+
+        let (variables, parameters, content) = Self::take_variables(content);
+        let mut configurations = self.parse_all_content(&content)?;
+        if let Some(defaults) = Self::parse_defaults(&parameters)? {
+            configurations.insert(0, defaults);
+        }
+        Ok(JailConf { variables, configurations })
+    }
+
+    /// Recover in lenient mode from content that ended inside a block, part way through a
+    /// directive or in a comment.  A trailing comment is kept, and a partial directive is
+    /// removed from its block, each with a warning and a recorded error.  Then the open blocks
+    /// are closed as if the content had ended with their closing braces.  Content that ended
+    /// in any other state is left for `take_configuration` to report.
+    fn recover_at_end_of_content(&mut self) {
+        if !self.lenient || self.block_depth() == 0 {
+            return;
+        }
+
+        let comment = self.state_stack.last() == Some(&ParserState::Comment);
+        if comment {
+            warn!("Content ends in a comment at line {} without closing its block", self.line);
+            self.state_stack.pop();
+            self.errors.push(ParseError::UnterminatedComment);
+        }
+        let truncated = matches!(self.state_stack.last(),
+                                 Some(ParserState::InDirective) | Some(ParserState::InQuotedString));
+        if !comment && !truncated {
+            return;
+        }
+
+        let mut config = std::mem::take(&mut self.config);
+        if truncated {
+            if let Some(directive) = self.current_block(&mut config).directives.pop() {
+                warn!("Dropping truncated directive {:?} at line {}, column {}",
+                      directive.raw, directive.span.0, directive.span.1);
+            }
+            self.errors.push(ParseError::TruncatedDirective);
+        }
+        while self.block_depth() > 0 {
+            self.end_block_transition(&mut config, '}');
+        }
+        self.config = config;
+    }
+
+    /// Tokenize content that contains any number of container configurations, such as a
+    /// jail.conf file that defines several jails.  Each time a top-level block ends, its
+    /// configuration is saved and the parser returns to the `ParserState::Starting` state to
+    /// read the next one.  Comments between blocks are kept with the block that follows them.
+    ///
+    /// * `content` - The content as a string
+    pub fn parse_all_content(
+        &mut self,
+        content: &str,
+    ) -> Result<Vec<Configuration>, ParseError> {
+
+        *self = ConfigParser::new();
+        let mut configs = vec![];
         let mut config = Configuration::default();
 
         for val in content.chars() {
-            let current_state = *self.state_stack.last().unwrap_or(&ParserState::Invalid);
+            self.consume(&mut config, val)?;
 
-            if current_state == ParserState::Invalid {
-                break;
+            if self.state_stack.last() == Some(&ParserState::EndBlock) {
+                self.state_stack.pop();
+                if !config.is_anonymous() {
+                    config.validate_name()?;
+                }
+                config.check_duplicates()?;
+                configs.push(std::mem::take(&mut config));
             }
+        }
 
-            let next_state = current_state.next_state(val);
-            if current_state != next_state {
-                self.handle_transition(&mut config, val, current_state, next_state);
-            } else if next_state == ParserState::InDirective {
-                if let Some(directive) = config.directives.last_mut() {
-                    directive.raw.push(val);
-                }
-            } else if next_state == ParserState::Name {
-                config.name.push(val);
+        self.end_of_content()?;
+        Ok(configs)
+    }
+
+    /// Process a single character.  Use the character to determine the next state as per the
+    /// state transitions.  If there is a state change, dispatch into the state change handling
+    /// functions.
+    ///
+    /// If we are reading a name, and there is no state transition, we preserve the read token
+    /// as part of the name.  If we are in the `ParserState::InDirective` or
+    /// `ParserState::InQuotedString` state, we save the token as part of the directive string,
+    /// joining lines that end in a backslash.  Comment text is saved with the block it is in.
+    ///
+    /// In lenient mode, an illegal character inside a block is recorded instead, and the
+    /// characters up to the next ';' are dropped.  A '}' also ends the skip and closes the block.
+    /// A ';' or '}' inside a quoted string does not end the skip.
+    ///
+    /// * `config` - The configuration parsed so far.
+    /// * `val` - The character to process.
+    fn consume(&mut self, config: &mut Configuration, val: char) -> Result<(), ParseError> {
+        if self.skipping && (self.skip_quoted || val != '}') {
+            if self.skip_quoted {
+                let escaped = std::mem::take(&mut self.escaped);
+                self.escaped = !escaped && val == '\\';
+                self.skip_quoted = escaped || val != '"';
+            } else {
+                self.skip_quoted = val == '"';
+                self.skipping = val != ';';
             }
+            self.advance(val);
+            return Ok(());
+        }
+        self.skipping = false;
+
+        let current_state = *self.state_stack.last().unwrap_or(&ParserState::Invalid);
+
+        // A backslash in a quoted string escapes the next character, so `\"` does not end it
+        let escaped = std::mem::take(&mut self.escaped);
+        let next_state = if escaped && current_state == ParserState::InQuotedString {
+            current_state
+        } else {
+            self.escaped = current_state == ParserState::InQuotedString && val == '\\';
+            current_state.next_state(val)
+        };
+        if next_state == ParserState::Invalid {
+            let error = ParseError::UnexpectedChar {
+                ch: val,
+                offset: self.offset,
+                line: self.line,
+                column: self.column,
+            };
+            if !self.lenient || self.block_depth() == 0 {
+                return Err(error);
+            }
+            self.errors.push(error);
+            self.skipping = val != ';';
+            self.advance(val);
+            return Ok(());
         }
 
-        Ok(config)
+        if current_state != next_state {
+            self.handle_transition(config, val, current_state, next_state);
+        } else if next_state == ParserState::InDirective || next_state == ParserState::InQuotedString {
+            if let Some(directive) = self.current_block(config).directives.last_mut() {
+                Self::push_directive_char(directive, val);
+            }
+        } else if next_state == ParserState::Name {
+            config.name.push(val);
+        } else if next_state == ParserState::Comment
+            && let Some((_, text)) = self.current_block(config).comments.last_mut() {
+            text.push(val);
+        }
+
+        self.advance(val);
+        Ok(())
+    }
+
+    /// Adds a character to the directive string.  A backslash immediately before the end of a
+    /// line continues the directive on the next line, so both the backslash and the line end
+    /// are dropped and the directive is kept as a single logical line.
+    ///
+    /// * `directive` - The directive being read.
+    /// * `token` - The character to add.
+    fn push_directive_char(directive: &mut ConfigItem, token: char) {
+        if token == '\n'
+            && let Some(continued) = directive.raw.strip_suffix('\\')
+                .or_else(|| directive.raw.strip_suffix("\\\r")) {
+            let length = continued.len();
+            directive.raw.truncate(length);
+        } else {
+            directive.raw.push(token);
+        }
+    }
+
+    /// Moves the position past the character just processed.  A newline, a lone carriage
+    /// return, or a carriage return and newline pair starts a new line and resets the column.
+    ///
+    /// * `token` - The character just processed.
+    fn advance(&mut self, token: char) {
+        self.offset += token.len_utf8();
+        if token == '\r' || (token == '\n' && !self.after_cr) {
+            self.line += 1;
+            self.column = 1;
+        } else if token != '\n' {
+            self.column += 1;
+        }
+        self.after_cr = token == '\r';
+    }
+
+    /// The number of configuration blocks that are currently open.
+    fn block_depth(&self) -> usize {
+        self.state_stack.iter().filter(|state| **state == ParserState::StartBlock).count()
+    }
+
+    /// Finds the innermost open block.  Nested blocks are always the last child of their
+    /// parent while they are being read, so we descend through the last child once for each
+    /// open block below the outermost one.
+    ///
+    /// * `config` - The configuration parsed so far.
+    fn current_block<'a>(&self, config: &'a mut Configuration) -> &'a mut Configuration {
+        let mut block = config;
+        for _ in 1..self.block_depth() {
+            if block.children.is_empty() {
+                break;
+            }
+            block = block.children.last_mut().unwrap();
+        }
+        block
+    }
+
+    /// Checks that the content ended in a terminal state, either before any configuration or
+    /// after the end of the configuration block.  A trailing comment is allowed in either of
+    /// those states.  Content that ends part way through a directive is reported as
+    /// `ParseError::TruncatedDirective`.
+    fn end_of_content(&self) -> Result<(), ParseError> {
+        let mut states = self.state_stack.iter().rev();
+        match states.next() {
+            Some(ParserState::Starting) | Some(ParserState::EndBlock) => Ok(()),
+            Some(ParserState::InDirective) | Some(ParserState::InQuotedString) => {
+                Err(ParseError::TruncatedDirective)
+            },
+            Some(ParserState::Comment) => match states.next() {
+                Some(ParserState::Starting) | Some(ParserState::EndBlock) => Ok(()),
+                _ => Err(ParseError::UnterminatedComment),
+            },
+            _ => Err(ParseError::UnterminatedBlock),
+        }
     }
 
     /// Handle the state transitions.  Given the current configuration, a token, a current state
@@ -127,24 +723,27 @@ impl ConfigParser {
                     ParserState::Seeking => self.seeking_transition(config, token),
                     ParserState::InDirective => self.in_directive_transition(config, token),
                     ParserState::Comment => self.start_comment_transition(config, token),
+                    ParserState::EndBlock => self.end_block_transition(config, token),
                     _ => {},
                 }
             },
             ParserState::EndBlock => {
-
+                if to == ParserState::Comment {
+                    self.start_comment_transition(config, token);
+                }
             },
             ParserState::Seeking => {
                 match to {
                     ParserState::InDirective => self.in_directive_transition(config, token),
                     ParserState::Comment => self.start_comment_transition(config, token),
                     ParserState::EndBlock => self.end_block_transition(config, token),
+                    ParserState::StartBlock => self.start_nested_block_transition(config, token),
                     _ => {},
                 }
             },
             ParserState::Comment => {
-                match to {
-                    ParserState::Seeking => self.end_comment_transition(config, token),
-                    _ => {},
+                if to == ParserState::Seeking {
+                    self.end_comment_transition(config, token);
                 }
             },
             ParserState::InDirective => {
@@ -152,10 +751,17 @@ impl ConfigParser {
                     ParserState::Comment => self.start_comment_transition(config, token),
                     ParserState::Seeking => self.seeking_transition(config, token),
                     ParserState::EndBlock => self.end_block_transition(config, token),
+                    ParserState::StartBlock => self.start_nested_block_transition(config, token),
+                    ParserState::InQuotedString => self.start_quote_transition(config, token),
                     _ => {},
                 }
 
             },
+            ParserState::InQuotedString => {
+                if to == ParserState::InDirective {
+                    self.end_quote_transition(config, token);
+                }
+            },
             ParserState::Invalid => {
 
             },
@@ -209,12 +815,17 @@ impl ConfigParser {
     /// * On a line on its own.
     /// * In the middle of a multi-line directive
     ///
-    /// * `_config` - The configuration parsed so far (not used).
+    /// The comment is recorded in the current block with the offset of the octothorpe, and
+    /// the text is filled in as it is read.
+    ///
+    /// * `config` - The configuration parsed so far.
     /// * `_token` - The token that initiated the transition (not used).
-    fn start_comment_transition(&mut self, _config: &mut Configuration, _token: char) {
+    fn start_comment_transition(&mut self, config: &mut Configuration, _token: char) {
         if self.state_stack.last() == Some(&ParserState::Name) {
             self.state_stack.pop();
         }
+        let offset = self.offset;
+        self.current_block(config).comments.push((offset, String::new()));
         self.state_stack.push(ParserState::Comment);
     }
 
@@ -231,12 +842,15 @@ impl ConfigParser {
     ///   and we're seeking the next directive.
     /// * We were seeking, read a directive or comment, and now we're seeking again.
     ///
-    /// * `_config` - The configuration parsed so far (not used).
+    /// When a directive is popped, it is complete and its values are extracted.
+    ///
+    /// * `config` - The configuration parsed so far.
     /// * `_token` - The token that initiated the transition (not used).
-    fn seeking_transition(&mut self, _config: &mut Configuration, _token: char) {
+    fn seeking_transition(&mut self, config: &mut Configuration, _token: char) {
         if let Some(state) = self.state_stack.last() {
             if state == &ParserState::InDirective {
                 self.state_stack.pop();
+                self.complete_directive(config);
             } else {
                 self.state_stack.push(ParserState::Seeking);
             }
@@ -247,19 +861,162 @@ impl ConfigParser {
     /// command or an assignment of a value.  But at this level, we just care about the string
     /// up to the semicolon that marks the end of the directive.  The token is stored as the
     /// first letter in the directive string and the `ParserState::InDirective` state is pushed
-    /// onto the state stack.
+    /// onto the state stack.  The current line and column are recorded as the directive's span.
     ///
     /// * `config` - The configuration parsed so far.
     /// * `token` - The token that initiated the transition.
     fn in_directive_transition(&mut self, config: &mut Configuration, token: char) {
         let mut item = ConfigItem::new("");
         item.raw.push(token);
-        config.directives.push(item);
+        item.span = (self.line, self.column);
+        self.current_block(config).directives.push(item);
         self.state_stack.push(ParserState::InDirective);
     }
 
-    /// Ends a configuration block for a container.  This is the end of the configuration and
-    /// nothing of note should come after.
+    /// Starts a quoted string inside a directive.  The quote is kept as part of the directive
+    /// string and the `ParserState::InQuotedString` state is pushed onto the stack, so that
+    /// structural characters are read literally until the closing quote.
+    ///
+    /// * `config` - The configuration parsed so far.
+    /// * `token` - The token that initiated the transition.
+    fn start_quote_transition(&mut self, config: &mut Configuration, token: char) {
+        if let Some(directive) = self.current_block(config).directives.last_mut() {
+            directive.raw.push(token);
+        }
+        self.state_stack.push(ParserState::InQuotedString);
+    }
+
+    /// Ends a quoted string inside a directive.  The closing quote is kept as part of the
+    /// directive string and we pop back to reading the directive.
+    ///
+    /// * `config` - The configuration parsed so far.
+    /// * `token` - The token that initiated the transition.
+    fn end_quote_transition(&mut self, config: &mut Configuration, token: char) {
+        self.state_stack.pop();
+        if let Some(directive) = self.current_block(config).directives.last_mut() {
+            directive.raw.push(token);
+        }
+    }
+
+    /// Extract the values from the directive that was just read.  The key is the run of
+    /// directive name characters at the start of the directive, and it must be followed by an
+    /// '=' (a `+=` is treated the same as '=') and the value.  Quotes around the value are
+    /// removed, and a comma separated value becomes a `ConfigValue::Array`.  A directive
+    /// without a value is recorded as `ConfigValue::Boolean(true)`.  The key also becomes the
+    /// name of the directive.  The raw text of the directive is left as it was read.
+    ///
+    /// * `config` - The configuration parsed so far.
+    fn complete_directive(&self, config: &mut Configuration) {
+        if let Some(directive) = self.current_block(config).directives.last_mut() {
+            let raw = directive.raw.trim().to_string();
+            let (key, rest) = Self::split_key(&raw);
+            let value = rest.strip_prefix("+=").or_else(|| rest.strip_prefix('='));
+            directive.name = key.to_string();
+
+            match value {
+                Some(value) => directive.add_value(key, Self::parse_value(value.trim())),
+                None if rest.is_empty() => directive.add_value(key, ConfigValue::Boolean(true)),
+                None => directive.add_value(&raw, ConfigValue::Boolean(true)),
+            }
+        }
+    }
+
+    /// Split a directive into the directive name and the remaining text, with the whitespace
+    /// between them removed.
+    ///
+    /// * `raw` - The trimmed directive text.
+    fn split_key(raw: &str) -> (&str, &str) {
+        let end = raw.find(|token| !is_directive_name_char(token)).unwrap_or(raw.len());
+        (&raw[..end], raw[end..].trim_start())
+    }
+
+    /// Convert the text to the right of the '=' into a value.
+    ///
+    /// * `value` - The trimmed value text.
+    fn parse_value(value: &str) -> ConfigValue {
+        let parts = Self::split_list(value);
+        if parts.len() > 1 {
+            ConfigValue::Array(parts.iter().map(|part| Self::unquote(part)).collect())
+        } else {
+            ConfigValue::String(Self::unquote(value))
+        }
+    }
+
+    /// Split a value on the commas that are not inside a quoted string.  Empty elements are
+    /// dropped.
+    ///
+    /// * `value` - The value text.
+    fn split_list(value: &str) -> Vec<&str> {
+        let mut parts = vec![];
+        let mut in_quotes = false;
+        let mut escaped = false;
+        let mut start = 0;
+
+        for (index, token) in value.char_indices() {
+            if escaped {
+                escaped = false;
+            } else if in_quotes && token == '\\' {
+                escaped = true;
+            } else if token == '"' {
+                in_quotes = !in_quotes;
+            } else if token == ',' && !in_quotes {
+                parts.push(value[start..index].trim());
+                start = index + 1;
+            }
+        }
+        parts.push(value[start..].trim());
+
+        parts.into_iter().filter(|part| !part.is_empty()).collect()
+    }
+
+    /// Remove a matching pair of double quotes from around a value.  Inside the quotes, a
+    /// backslash escapes the character after it, so `\"` is a quote and `\\` a backslash.
+    ///
+    /// * `value` - The value text.
+    fn unquote(value: &str) -> String {
+        let Some(inner) = value.strip_prefix('"').and_then(|inner| inner.strip_suffix('"')) else {
+            return value.to_string();
+        };
+
+        let mut out = String::with_capacity(inner.len());
+        let mut escaped = false;
+        for token in inner.chars() {
+            if escaped || token != '\\' {
+                out.push(token);
+            }
+            escaped = !escaped && token == '\\';
+        }
+        out
+    }
+
+    /// Start a block nested inside the current block.  If we were reading a directive, the
+    /// directive text is really the name of the nested block, so the directive is removed and
+    /// its trimmed text used as the block name.  If we were seeking, the nested block has no
+    /// name.  The nested block is added as a child of the current block and the
+    /// `ParserState::StartBlock` state is pushed onto the stack.
+    ///
+    /// * `config` - The configuration parsed so far.
+    /// * `_token` - The token that initiated the transition (not used).
+    fn start_nested_block_transition(&mut self, config: &mut Configuration, _token: char) {
+        let from_directive = self.state_stack.last() == Some(&ParserState::InDirective);
+        if from_directive {
+            self.state_stack.pop();
+        }
+
+        let parent = self.current_block(config);
+        let mut child = Configuration::default();
+        if from_directive && let Some(directive) = parent.directives.pop() {
+            child.set_name(directive.raw.trim());
+        }
+        parent.add_child(child);
+
+        self.state_stack.push(ParserState::StartBlock);
+    }
+
+    /// Ends a configuration block.  The stack is unwound to the start of the block.  If that
+    /// was a nested block, we are back in the enclosing block and carry on with whatever state
+    /// we were in when the nested block started.  Otherwise, this is the end of the container
+    /// configuration and nothing of note should come after.
     ///
     /// * `_config` - The configuration parsed so far (not used).
     /// * `_token` - The token that initiated the transition (not used).
@@ -270,7 +1027,9 @@ impl ConfigParser {
             }
         }
 
-        self.state_stack.push(ParserState::EndBlock);
+        if self.block_depth() == 0 {
+            self.state_stack.push(ParserState::EndBlock);
+        }
     }
 
     /// Ends a comment.  Whatever was happening when we were interrupted by a comment, we return
@@ -445,7 +1204,26 @@ mod test {
     }
 
     #[test]
-    fn test_basic_parsing() -> Result<(), Box<dyn std::error::Error>> {
+    fn test_nested_block_transition() {
+        let mut config_parser = ConfigParser::new();
+        let mut configuration = Configuration::default();
+        config_parser.state_stack.push(ParserState::StartBlock);
+        config_parser.state_stack.push(ParserState::Seeking);
+        config_parser.handle_transition(&mut configuration, 'i', ParserState::Seeking, ParserState::InDirective);
+        configuration.directives.last_mut().unwrap().raw.push_str("nner ");
+
+        config_parser.handle_transition(&mut configuration, '{', ParserState::InDirective, ParserState::StartBlock);
+        assert_eq!(config_parser.state_stack, vec![ParserState::Starting, ParserState::StartBlock, ParserState::Seeking,
+                                                   ParserState::StartBlock]);
+        assert_eq!(configuration.directives.len(), 0);
+        assert_eq!(configuration.children[0].name, "inner".to_string());
+
+        config_parser.handle_transition(&mut configuration, '}', ParserState::StartBlock, ParserState::EndBlock);
+        assert_eq!(config_parser.state_stack, vec![ParserState::Starting, ParserState::StartBlock, ParserState::Seeking]);
+    }
+
+    #[test]
+    fn test_basic_parsing() -> Result<(), ParseError> {
         let mut config_parser = ConfigParser::new();
         let configuration = config_parser.parse_content(r#"fordo {
             # foo the bar
@@ -460,4 +1238,562 @@ mod test {
 
         Ok(())
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_parse_stats() -> Result<(), ParseError> {
+        let content = r#"fordo {
+            # foo the bar
+            bobo;
+            coco="dodo";
+        }"#;
+        let mut config_parser = ConfigParser::new();
+        let (configuration, stats) = config_parser.parse_content_with_stats(content)?;
+
+        assert_eq!(configuration.name, "fordo".to_string());
+        assert_eq!(stats.directives, 2);
+        assert_eq!(stats.comments, 1);
+        assert_eq!(stats.bytes, content.len());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_feed() -> Result<(), ParseError> {
+        let content = r#"fordo {
+            # foo the bar
+            bobo;
+            coco="dodo";
+        }"#;
+
+        let mut config_parser = ConfigParser::new();
+        let mut buffer = [0; 4];
+        for val in content.chars() {
+            config_parser.feed(val.encode_utf8(&mut buffer))?;
+        }
+        let fed = config_parser.finish()?;
+
+        let batch = ConfigParser::new().parse_content(content)?;
+
+        assert_eq!(fed, batch);
+
+        let mut config_parser = ConfigParser::new();
+        config_parser.feed("fordo {\n    bobo;")?;
+        assert!(matches!(config_parser.finish(), Err(ParseError::UnterminatedBlock)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parser_reuse() -> Result<(), ParseError> {
+        let second = r#"gimli {
+            mount.devfs;
+            host.hostname="gimli";
+        }"#;
+
+        let mut config_parser = ConfigParser::new();
+        assert!(config_parser.parse_content("aragorn {\n    exec.start = \"/bin/sh").is_err());
+        let reused = config_parser.parse_content(second)?;
+
+        let fresh = ConfigParser::new().parse_content(second)?;
+
+        assert_eq!(reused.name, fresh.name);
+        assert_eq!(reused.directives, fresh.directives);
+        assert_eq!(config_parser.state_stack, vec![ParserState::Starting, ParserState::EndBlock]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_trailing_comment() -> Result<(), ParseError> {
+        let mut config_parser = ConfigParser::new();
+        let configuration = config_parser.parse_content("fordo {\n    bobo;\n}\n# the end")?;
+
+        assert_eq!(configuration.name, "fordo".to_string());
+        assert_eq!(configuration.directives.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unterminated_block() {
+        let mut config_parser = ConfigParser::new();
+        let result = config_parser.parse_content("fordo {\n    bobo;\n    coco=\"dodo\";\n");
+        assert!(matches!(result, Err(ParseError::UnterminatedBlock)));
+
+        let mut config_parser = ConfigParser::new();
+        let result = config_parser.parse_content("fordo {\n    bobo; # no closing brace");
+        assert!(matches!(result, Err(ParseError::UnterminatedComment)));
+    }
+
+    #[test]
+    fn test_lenient_parsing() -> Result<(), ParseError> {
+        let mut config_parser = ConfigParser::new();
+        let parsed = config_parser.parse_content_lenient(r#"fordo {
+            bobo;
+            @coco = "dodo";
+            ip4.addr = 10.0.0.1;
+        }"#)?;
+
+        let configuration = &parsed.configuration;
+        assert_eq!(configuration.name, "fordo".to_string());
+        assert_eq!(configuration.directives.len(), 2);
+        assert_eq!(configuration.directives[0].raw, "bobo".to_string());
+        assert_eq!(configuration.directives[1].raw, "ip4.addr = 10.0.0.1".to_string());
+        assert_eq!(parsed.errors.len(), 1);
+        assert!(matches!(parsed.errors[0], ParseError::UnexpectedChar { ch: '@', line: 3, column: 13, .. }));
+
+        let parsed = config_parser.parse_content_lenient("fordo {\n    @x = \"a;b}\\\"c;\" junk;\n    coco;\n}")?;
+        assert_eq!(parsed.configuration.directives.len(), 1);
+        assert_eq!(parsed.configuration.directives[0].raw, "coco".to_string());
+        assert_eq!(parsed.errors.len(), 1);
+
+        let parsed = config_parser.parse_content_lenient("fordo {\n    bobo;\n    !bad}")?;
+        assert_eq!(parsed.configuration.directives.len(), 1);
+        assert_eq!(parsed.errors.len(), 1);
+
+        assert!(matches!(config_parser.parse_content_lenient("@fordo { bobo; }"),
+                         Err(ParseError::UnexpectedChar { ch: '@', .. })));
+        assert!(config_parser.parse_content(r#"fordo { @coco = "dodo"; }"#).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_normalized_directive() -> Result<(), ParseError> {
+        let mut config_parser = ConfigParser::new();
+        let configuration = config_parser.parse_content("fordo {\n    coco   =   \"do   do\";\n    ip4.addr\t+=10.0.0.1,  10.0.0.2;\n    persist;\n}")?;
+
+        assert_eq!(configuration.directives[0].raw, "coco   =   \"do   do\"".to_string());
+        assert_eq!(configuration.directives[0].normalized(), "coco = \"do   do\"".to_string());
+        assert_eq!(configuration.directives[1].raw, "ip4.addr\t+=10.0.0.1,  10.0.0.2".to_string());
+        assert_eq!(configuration.directives[1].normalized(), "ip4.addr += 10.0.0.1, 10.0.0.2".to_string());
+        assert_eq!(configuration.directives[2].normalized(), "persist".to_string());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_truncated_directive() -> Result<(), ParseError> {
+        let content = "fordo {\n    bobo;\n    coco = \"do";
+
+        let result = ConfigParser::new().parse_content(content);
+        assert!(matches!(result, Err(ParseError::TruncatedDirective)));
+        let result = ConfigParser::new().parse_content("fordo {\n    bobo;\n    ip4.addr = 10.0");
+        assert!(matches!(result, Err(ParseError::TruncatedDirective)));
+
+        let parsed = ConfigParser::new().parse_content_lenient(content)?;
+        assert_eq!(parsed.configuration.name, "fordo".to_string());
+        assert_eq!(parsed.configuration.directives.len(), 1);
+        assert_eq!(parsed.configuration.directives[0].raw, "bobo".to_string());
+        assert_eq!(parsed.errors.len(), 1);
+        assert!(matches!(parsed.errors[0], ParseError::TruncatedDirective));
+
+        let parsed = ConfigParser::new().parse_content_lenient("fordo {\n    bobo;\n    inner {\n        coco")?;
+        assert_eq!(parsed.configuration.directives.len(), 1);
+        assert_eq!(parsed.configuration.children.len(), 1);
+        assert!(parsed.configuration.children[0].directives.is_empty());
+        assert_eq!(parsed.errors.len(), 1);
+
+        let content = "fordo {\n    bobo;\n    # the end";
+        assert!(matches!(ConfigParser::new().parse_content(content), Err(ParseError::UnterminatedComment)));
+        let parsed = ConfigParser::new().parse_content_lenient(content)?;
+        assert_eq!(parsed.configuration.directives.len(), 1);
+        assert_eq!(parsed.configuration.comments.len(), 1);
+        assert!(matches!(parsed.errors[..], [ParseError::UnterminatedComment]));
+
+        // Nothing is recovered from a block that ends between directives, or from a bare name
+        let result = ConfigParser::new().parse_content_lenient("fordo {\n    bobo;\n");
+        assert!(matches!(result, Err(ParseError::UnterminatedBlock)));
+        let result = ConfigParser::new().parse_content_lenient("fordo");
+        assert!(matches!(result, Err(ParseError::UnterminatedBlock)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_illegal_leading_character() {
+        let mut config_parser = ConfigParser::new();
+        let result = config_parser.parse_content("  @fordo {\n    bobo;\n}");
+        assert!(matches!(result, Err(ParseError::UnexpectedChar { ch: '@', offset: 2, line: 1, column: 3 })));
+    }
+
+    #[test]
+    fn test_directive_span() -> Result<(), ParseError> {
+        let mut config_parser = ConfigParser::new();
+        let configuration = config_parser.parse_content("fordo {\n    bobo;\n    coco=\"dodo\";\n}")?;
+
+        assert_eq!(configuration.directives[0].span, (2, 5));
+        assert_eq!(configuration.directives[1].span, (3, 5));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_error_position() {
+        let mut config_parser = ConfigParser::new();
+        let result = config_parser.parse_content("fordo {\n    bobo;\n  } fordo");
+        assert!(matches!(result, Err(ParseError::UnexpectedChar { ch: 'f', line: 3, column: 5, .. })));
+    }
+
+    #[test]
+    fn test_nested_blocks() -> Result<(), ParseError> {
+        let mut config_parser = ConfigParser::new();
+        let configuration = config_parser.parse_content("outer { inner { foo; } bar; }")?;
+
+        assert_eq!(configuration.name, "outer".to_string());
+        assert_eq!(configuration.directives.len(), 1);
+        assert_eq!(configuration.directives[0].raw, "bar".to_string());
+        assert_eq!(configuration.children.len(), 1);
+        assert_eq!(configuration.children[0].name, "inner".to_string());
+        assert_eq!(configuration.children[0].directives.len(), 1);
+        assert_eq!(configuration.children[0].directives[0].raw, "foo".to_string());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_directive_values() -> Result<(), ParseError> {
+        let mut config_parser = ConfigParser::new();
+        let configuration = config_parser.parse_content(r#"fordo {
+            bobo;
+            coco="dodo";
+            ip4.addr = 10.0.0.1, "10.0.0.2";
+            exec.start += "/bin/sh /etc/rc";
+        }"#)?;
+
+        let directives = &configuration.directives;
+        assert_eq!(directives[0].values.get("bobo"), Some(&ConfigValue::Boolean(true)));
+        assert_eq!(directives[1].values.get("coco"), Some(&ConfigValue::String("dodo".to_string())));
+        assert_eq!(directives[1].raw, "coco=\"dodo\"".to_string());
+        assert_eq!(directives[1].name, "coco".to_string());
+        assert_eq!(directives[2].values.get("ip4.addr"),
+                   Some(&ConfigValue::Array(vec!["10.0.0.1".to_string(), "10.0.0.2".to_string()])));
+        assert_eq!(directives[2].name, "ip4.addr".to_string());
+        assert_eq!(directives[3].values.get("exec.start"),
+                   Some(&ConfigValue::String("/bin/sh /etc/rc".to_string())));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_quoted_strings() -> Result<(), ParseError> {
+        let mut config_parser = ConfigParser::new();
+        let configuration = config_parser.parse_content(r#"fordo {
+            exec.start = "sh -c 'foo; bar'";
+            exec.stop = "echo } # {";
+        }"#)?;
+
+        assert_eq!(configuration.directives.len(), 2);
+        assert_eq!(configuration.directives[0].raw, r#"exec.start = "sh -c 'foo; bar'""#.to_string());
+        assert_eq!(configuration.directives[0].values.get("exec.start"),
+                   Some(&ConfigValue::String("sh -c 'foo; bar'".to_string())));
+        assert_eq!(configuration.directives[1].raw, r#"exec.stop = "echo } # {""#.to_string());
+        assert_eq!(config_parser.state_stack, vec![ParserState::Starting, ParserState::EndBlock]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_line_continuation() -> Result<(), ParseError> {
+        let mut config_parser = ConfigParser::new();
+        let configuration = config_parser.parse_content(
+            "fordo {\n    exec.prestart = \"ifconfig epair0 create \\\nup \\\ndescr jail\";\n}")?;
+
+        let raw = &configuration.directives[0].raw;
+        assert_eq!(raw, &"exec.prestart = \"ifconfig epair0 create up descr jail\"".to_string());
+        assert!(!raw.contains('\\'));
+        assert!(!raw.contains('\n'));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_dotted_directive_names() -> Result<(), ParseError> {
+        let mut config_parser = ConfigParser::new();
+        let configuration = config_parser.parse_content(r#"fordo {
+            allow.mount.zfs = true;
+            exec-timeout=30;
+            .mount_devfs;
+        }"#)?;
+
+        let directives = &configuration.directives;
+        assert_eq!(directives[0].values.get("allow.mount.zfs"), Some(&ConfigValue::String("true".to_string())));
+        assert_eq!(directives[1].values.get("exec-timeout"), Some(&ConfigValue::String("30".to_string())));
+        assert_eq!(directives[2].values.get(".mount_devfs"), Some(&ConfigValue::Boolean(true)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_comments() -> Result<(), ParseError> {
+        let content = "# jail for fordo\nfordo {\n    #   \n    bobo; # trailing\n}\n";
+        let mut config_parser = ConfigParser::new();
+        let configuration = config_parser.parse_content(content)?;
+
+        assert_eq!(configuration.comments, vec![
+            (0, " jail for fordo".to_string()),
+            (29, "   ".to_string()),
+            (44, " trailing".to_string()),
+        ]);
+        assert_eq!(&content[29..30], "#");
+        assert_eq!(&content[44..45], "#");
+        assert_eq!(configuration.directives.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_comment_line_endings() -> Result<(), ParseError> {
+        let configuration = ConfigParser::new().parse_content("# crlf\r\nfordo {\r\n    # note\r\n    bobo;\r\n}\r\n")?;
+        assert_eq!(configuration.name, "fordo".to_string());
+        assert_eq!(configuration.comments[0].1, " crlf".to_string());
+        assert_eq!(configuration.comments[1].1, " note".to_string());
+        assert_eq!(configuration.directives.len(), 1);
+
+        let configuration = ConfigParser::new().parse_content("# cr\rfordo {\r    # note\r    bobo;\r}\r")?;
+        assert_eq!(configuration.name, "fordo".to_string());
+        assert_eq!(configuration.comments[1].1, " note".to_string());
+        assert_eq!(configuration.directives.len(), 1);
+
+        for content in ["fordo {\r\n    # note\r\n  $", "fordo {\r    # note\r  $"] {
+            let result = ConfigParser::new().parse_content(content);
+            assert!(matches!(result, Err(ParseError::UnexpectedChar { ch: '$', line: 3, column: 3, .. })));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_directive_starting_with_digit() -> Result<(), ParseError> {
+        let first = ConfigParser::new().parse_content("fordo {4k_something;}")?;
+        let later = ConfigParser::new().parse_content("fordo {bobo; 4k_something;}")?;
+
+        assert_eq!(first.directives[0].raw, "4k_something".to_string());
+        assert_eq!(later.directives[1].raw, "4k_something".to_string());
+        assert_eq!(first.directives[0].values, later.directives[1].values);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tabs_and_spaces() -> Result<(), ParseError> {
+        let spaces = ConfigParser::new().parse_content("fordo {\n    bobo;\n    coco = \"dodo\";\n}\n")?;
+        let tabs = ConfigParser::new().parse_content("fordo\t{\n\tbobo;\n\tcoco\t=\t\"dodo\";\n}\n")?;
+
+        assert_eq!(tabs.name, spaces.name);
+        assert_eq!(tabs.directives.len(), spaces.directives.len());
+        for (left, right) in tabs.directives.iter().zip(spaces.directives.iter()) {
+            assert_eq!(left.values, right.values);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unicode_whitespace() -> Result<(), ParseError> {
+        let configuration = ConfigParser::new().parse_content("fordo\u{A0}{\u{0B}bobo;\u{2003}coco = dodo;\u{A0}}")?;
+
+        assert_eq!(configuration.name, "fordo".to_string());
+        assert_eq!(configuration.directives.len(), 2);
+        assert_eq!(configuration.directives[0].get_bool("bobo"), Some(true));
+        assert_eq!(configuration.directives[1].get_string("coco"), Some("dodo"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_empty_block() -> Result<(), ParseError> {
+        for content in ["empty { }", "empty {}", "empty {\n    # nothing yet\n}\n"] {
+            let configuration = ConfigParser::new().parse_content(content)?;
+            assert_eq!(configuration.name, "empty".to_string());
+            assert!(configuration.directives.is_empty());
+            assert!(configuration.children.is_empty());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_no_configuration() -> Result<(), ParseError> {
+        for content in ["", "  \n\t\n", "# nothing here\n# or here\n", "# no trailing newline"] {
+            let result = ConfigParser::new().parse_content(content);
+            assert!(matches!(result, Err(ParseError::NoConfiguration)), "{:?}: {:?}", content, result);
+            assert!(ConfigParser::new().parse_all_content(content)?.is_empty());
+        }
+
+        let result = ConfigParser::new().parse_content("fordo ");
+        assert!(matches!(result, Err(ParseError::UnterminatedBlock)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_duplicate_key() -> Result<(), ParseError> {
+        let content = "legolas {\n    host = new;\n    path = \"/jails/legolas\";\n    host = inherit;\n}\n";
+
+        let error = ConfigParser::new().parse_content(content).unwrap_err();
+        assert_eq!(error.to_string(), "duplicate directive \"host\" in block legolas");
+        assert!(matches!(ConfigParser::new().parse_all_content(content), Err(ParseError::DuplicateKey { .. })));
+
+        let parsed = ConfigParser::new().parse_content_lenient(content)?;
+        assert_eq!(parsed.configuration.len(), 3);
+        assert!(matches!(parsed.errors[0], ParseError::DuplicateKey { .. }));
+
+        let configuration = ConfigParser::new().parse_content("legolas {\n    ip4.addr = 10.0.0.1;\n    ip4.addr += 10.0.0.2;\n}\n")?;
+        assert_eq!(configuration.len(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_multiple_blocks() -> Result<(), ParseError> {
+        let mut config_parser = ConfigParser::new();
+        let configurations = config_parser.parse_all_content("a { x; } b { y; }")?;
+
+        assert_eq!(configurations.len(), 2);
+        assert_eq!(configurations[0].name, "a".to_string());
+        assert_eq!(configurations[0].directives[0].raw, "x".to_string());
+        assert_eq!(configurations[1].name, "b".to_string());
+        assert_eq!(configurations[1].directives[0].raw, "y".to_string());
+
+        let result = ConfigParser::new().parse_all_content("a { x; } b { y;");
+        assert!(matches!(result, Err(ParseError::UnterminatedBlock)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unicode_name() -> Result<(), ParseError> {
+        let configuration = ConfigParser::new().parse_content("legolas { persist; }")?;
+        assert_eq!(configuration.name, "legolas".to_string());
+
+        let configuration = ConfigParser::new().parse_content("élan { persist; }")?;
+        assert_eq!(configuration.name, "élan".to_string());
+        assert_eq!(configuration.directives.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_separated_name() -> Result<(), ParseError> {
+        let configuration = ConfigParser::new().parse_content("web-01 {}")?;
+        assert_eq!(configuration.name, "web-01".to_string());
+        assert!(configuration.directives.is_empty());
+
+        let configuration = ConfigParser::new().parse_content("web_1 { persist; }")?;
+        assert_eq!(configuration.name, "web_1".to_string());
+        assert_eq!(configuration.directives.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_name() {
+        let result = ConfigParser::new().parse_content("jails/web { persist; }");
+        assert!(matches!(result, Err(ParseError::UnexpectedChar { ch: '/', .. })));
+
+        let result = ConfigParser::new().parse_content("web.local { persist; }");
+        assert!(matches!(result, Err(ParseError::UnexpectedChar { ch: '.', .. })));
+    }
+
+    #[test]
+    fn test_anonymous_block() -> Result<(), ParseError> {
+        let configuration = ConfigParser::new().parse_content("{ persist; }")?;
+        assert!(configuration.is_anonymous());
+        assert_eq!(configuration.directives[0].get_bool("persist"), Some(true));
+        assert_eq!(configuration.to_config_string(), "{\n\tpersist;\n}\n");
+
+        let configurations = ConfigParser::new().parse_all_content("{ persist; } web { x; }")?;
+        assert_eq!(configurations.len(), 2);
+        assert!(configurations[0].is_anonymous());
+        assert_eq!(configurations[1].name, "web".to_string());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_includes() -> Result<(), ParseError> {
+        let dir = tempfile::tempdir()?;
+        fs::create_dir(dir.path().join("jail.conf.d"))?;
+        fs::write(dir.path().join("jail.conf"), "# jails\n.include \"jail.conf.d/*.conf\";\nparent {\n\tpersist;\n}\n")?;
+        fs::write(dir.path().join("jail.conf.d/child.conf"), "child {\n\tpath = \"/jails/child\";\n}\ninclude \"../jail.conf\";\n")?;
+        fs::write(dir.path().join("jail.conf.d/child.txt"), "ignored { persist; }\n")?;
+        fs::write(dir.path().join("jail.conf.d/fragment.conf"), "{ persist; }\n")?;
+
+        let configurations = ConfigParser::new().parse_all_file_with_includes(dir.path().join("jail.conf"))?;
+
+        assert_eq!(configurations.len(), 3);
+        assert_eq!(configurations[0].name, "parent".to_string());
+        assert_eq!(configurations[1].name, "child".to_string());
+        assert_eq!(configurations[2].name, "fragment".to_string());
+        assert_eq!(configurations[1].directives[0].get_string("path"), Some("/jails/child"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_quoted_variables() -> Result<(), ParseError> {
+        let jail_conf = ConfigParser::new().parse_all_content_with_variables(concat!(
+            "$url = \"http://h/#x\";\n",
+            "$open = \"{\";\n",
+            "web {\n\tpath = \"/jails/web\";\n}\n",
+            "$after = \"yes\";\n",
+        ))?;
+
+        assert_eq!(jail_conf.variables.get("url").map(String::as_str), Some("http://h/#x"));
+        assert_eq!(jail_conf.variables.get("open").map(String::as_str), Some("{"));
+        assert_eq!(jail_conf.variables.get("after").map(String::as_str), Some("yes"));
+        assert_eq!(jail_conf.configurations.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_top_level_parameters() -> Result<(), ParseError> {
+        let jail_conf = ConfigParser::new().parse_all_content_with_variables(concat!(
+            "$root = \"/jails\";\n",
+            "path = \"$root/$name\";\n",
+            "persist;\n",
+            "web {\n\thost.hostname = \"web\";\n}\n",
+        ))?;
+
+        assert_eq!(jail_conf.variables.keys().collect::<Vec<_>>(), vec!["root"]);
+        assert_eq!(jail_conf.configurations.len(), 2);
+        let defaults = &jail_conf.configurations[0];
+        assert!(defaults.is_template());
+        assert_eq!(defaults.get_string("path"), Some("$root/$name"));
+        assert_eq!(defaults.get_value("persist").as_deref(), Some(&ConfigValue::Boolean(true)));
+        assert_eq!(jail_conf.configurations[1].name, "web".to_string());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_take_includes() {
+        let (includes, content) = ConfigParser::take_includes(concat!(
+            "include \"conf.d/#1.conf\"; # first\n",
+            "include {\n\tpersist;\n}\n",
+            ".include \"other.conf\";\n",
+        ));
+
+        assert_eq!(includes, vec!["conf.d/#1.conf".to_string(), "other.conf".to_string()]);
+        assert_eq!(content.lines().next().map(str::trim), Some("# first"));
+        assert!(!content.contains("other.conf"));
+
+        let configurations = ConfigParser::new().parse_all_content(&content).unwrap();
+        assert_eq!(configurations.len(), 1);
+        assert_eq!(configurations[0].name, "include".to_string());
+    }
+
+    #[test]
+    fn test_parse_example_file() -> Result<(), ParseError> {
+        let mut config_parser = ConfigParser::new();
+        let configurations = config_parser.parse_all_file("examples/aragorn.conf")?;
+
+        assert_eq!(configurations.len(), 1);
+        assert_eq!(configurations[0].name, "aragorn".to_string());
+        assert_eq!(configurations[0].directives.len(), 11);
+
+        Ok(())
+    }
+}
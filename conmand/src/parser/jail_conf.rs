@@ -0,0 +1,77 @@
+//!
+//! Copyright (c) 2026, Paul C. Hoehne
+//!
+//! Redistribution and use in source and binary forms, with or without modification, are 
+//! permitted provided that the following conditions are met:
+//!
+//!   Redistributions of source code must retain the above copyright notice, this list of 
+//!   conditions and the following disclaimer.
+//! 
+//!   Redistributions in binary form must reproduce the above copyright notice, this list of 
+//!   conditions and the following disclaimer in the documentation and/or other materials 
+//!   provided with the distribution.
+//!
+//! THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY 
+//! EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF 
+//! MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL 
+//! THE COPYRIGHT OWNER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, 
+//! SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT 
+//! OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) 
+//! HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, 
+//! OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS 
+//! SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+//! 
+
+use std::collections::HashMap;
+use crate::parser::config::Configuration;
+use crate::parser::parse_error::ParseError;
+
+/// The contents of a whole jail.conf file: the variables defined at the top level, outside of
+/// any block, and the container configurations in the order they were declared.
+///
+/// * `variables` - The defined variables, keyed by name without the '$'.
+/// * `configurations` - The container configurations.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct JailConf {
+    pub variables: HashMap<String, String>,
+    pub configurations: Vec<Configuration>,
+}
+
+impl JailConf {
+
+    /// Expand the variables in every configuration, using the variables defined in the file
+    /// before those in the environment.  See `Configuration::interpolate_with`.
+    ///
+    /// * `strict` - True if a variable without a value is an error.
+    pub fn interpolate(&mut self, strict: bool) -> Result<(), ParseError> {
+        for configuration in &mut self.configurations {
+            configuration.interpolate_with(&self.variables, strict)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::config_parser::ConfigParser;
+
+    #[test]
+    fn test_variables() -> Result<(), ParseError> {
+        let mut jail_conf = ConfigParser::new().parse_all_content_with_variables(r#"# defaults
+$domain = "example.org";
+web {
+    host.hostname = "$name.${domain}";
+}
+"#)?;
+
+        assert_eq!(jail_conf.variables.get("domain").map(String::as_str), Some("example.org"));
+        assert_eq!(jail_conf.configurations.len(), 1);
+        assert_eq!(jail_conf.configurations[0].get_string("host.hostname"), Some("$name.${domain}"));
+
+        jail_conf.interpolate(true)?;
+        assert_eq!(jail_conf.configurations[0].get_string("host.hostname"), Some("web.example.org"));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,131 @@
+//!
+//! Copyright (c) 2026, Paul C. Hoehne
+//!
+//! Redistribution and use in source and binary forms, with or without modification, are
+//! permitted provided that the following conditions are met:
+//!
+//!   Redistributions of source code must retain the above copyright notice, this list of
+//!   conditions and the following disclaimer.
+//!
+//!   Redistributions in binary form must reproduce the above copyright notice, this list of
+//!   conditions and the following disclaimer in the documentation and/or other materials
+//!   provided with the distribution.
+//!
+//! THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY
+//! EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF
+//! MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL
+//! THE COPYRIGHT OWNER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+//! SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT
+//! OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION)
+//! HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+//! OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+//! SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+//!
+
+use std::fmt;
+
+/// The errors that can be raised while parsing a container configuration.
+///
+/// * UnexpectedChar - A character that is not legal in the current parser state, with its byte
+///   offset and its line and column (both starting at 1).
+/// * UnterminatedBlock - The content ended before the configuration block was closed.
+/// * UnterminatedComment - The content ended in a comment inside an open block.
+/// * TruncatedDirective - The content ended part way through a directive, before its ';'.
+/// * NoConfiguration - The content has no configuration block, only whitespace and comments.
+/// * InvalidName - The container name is not a legal jail name.
+/// * DuplicateKey - A block assigns the same directive more than once, with the block name and
+///   the directive name.
+/// * InvalidInclude - An `include` directive names a path that is not a valid glob pattern.
+/// * UndefinedVariable - A value refers to a variable that has no value, with the variable name.
+/// * Io - The configuration could not be read.
+#[derive(Debug)]
+pub enum ParseError {
+    UnexpectedChar { ch: char, offset: usize, line: usize, column: usize },
+    UnterminatedBlock,
+    UnterminatedComment,
+    TruncatedDirective,
+    NoConfiguration,
+    InvalidName(NameError),
+    DuplicateKey { block: String, key: String },
+    InvalidInclude(String),
+    UndefinedVariable(String),
+    Io(std::io::Error),
+}
+
+/// The reasons a container name is not a legal jail name.
+///
+/// * Empty - The block has no name.
+/// * InvalidChar - The name contains a character jails do not allow, such as `.`, which
+///   separates the names of hierarchical jails, or `/`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum NameError {
+    Empty,
+    InvalidChar(char),
+}
+
+impl fmt::Display for NameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NameError::Empty => write!(f, "container name is empty"),
+            NameError::InvalidChar(ch) => write!(f, "container name contains {:?}", ch),
+        }
+    }
+}
+
+impl std::error::Error for NameError {}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::UnexpectedChar { ch, line, column, .. } => {
+                write!(f, "unexpected character {:?} at line {}, column {}", ch, line, column)
+            },
+            ParseError::UnterminatedBlock => write!(f, "unterminated configuration block"),
+            ParseError::UnterminatedComment => write!(f, "unterminated comment"),
+            ParseError::TruncatedDirective => write!(f, "truncated directive at end of content"),
+            ParseError::NoConfiguration => write!(f, "no configuration block"),
+            ParseError::InvalidName(error) => write!(f, "invalid container name: {}", error),
+            ParseError::DuplicateKey { block, key } => {
+                write!(f, "duplicate directive {:?} in block {}", key, block)
+            },
+            ParseError::InvalidInclude(pattern) => write!(f, "invalid include pattern {:?}", pattern),
+            ParseError::UndefinedVariable(name) => write!(f, "undefined variable {:?}", name),
+            ParseError::Io(error) => write!(f, "unable to read configuration: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ParseError::Io(error) => Some(error),
+            ParseError::InvalidName(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ParseError {
+    fn from(error: std::io::Error) -> Self {
+        ParseError::Io(error)
+    }
+}
+
+impl From<NameError> for ParseError {
+    fn from(error: NameError) -> Self {
+        ParseError::InvalidName(error)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        let error = ParseError::UnexpectedChar { ch: '.', offset: 3, line: 1, column: 4 };
+        assert_eq!(error.to_string(), "unexpected character '.' at line 1, column 4");
+        assert_eq!(ParseError::UnterminatedBlock.to_string(), "unterminated configuration block");
+        assert_eq!(ParseError::InvalidName(NameError::InvalidChar('/')).to_string(), "invalid container name: container name contains '/'");
+    }
+}
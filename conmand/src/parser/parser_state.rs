@@ -22,6 +22,71 @@
 //! SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 //!
 
+/// Returns true if the character can be part of a directive name.  Directive names are made up
+/// of alpha-numeric characters, with '.', '-' and '_' used as separators (e.g. `ip4.addr` or
+/// `allow.mount.zfs`).
+///
+/// * `token` - The character to test.
+pub fn is_directive_name_char(token: char) -> bool {
+    matches!(CharClass::classify(token), CharClass::AlphaNum | CharClass::Dot | CharClass::NamePunct)
+}
+
+/// The kinds of character the tokenizer tells apart.  Every character belongs to exactly one
+/// class, and the state transitions depend only on the class of a character.
+///
+/// * Whitespace - Any Unicode whitespace other than a line end, as `char::is_whitespace`
+///   defines it.  This includes tabs, vertical tabs, form feeds and non-breaking spaces, and
+///   matches what `str::trim` removes from directive text.
+/// * LineEnd - A newline or carriage return.
+/// * AlphaNum - Any Unicode letter or digit.
+/// * Dot - '.', which separates the parts of a directive name but may not appear in a
+///   container name, as jail(8) uses it to separate the names of hierarchical jails.
+/// * NamePunct - The separators allowed in both directive and container names: '-' and '_'.
+/// * Open - '{'
+/// * Close - '}'
+/// * Semicolon - ';'
+/// * Hash - '#'
+/// * Quote - '"'
+/// * Wildcard - '*', the name of the template block.
+/// * Other - Anything else.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum CharClass {
+    Whitespace,
+    LineEnd,
+    AlphaNum,
+    Dot,
+    NamePunct,
+    Open,
+    Close,
+    Semicolon,
+    Hash,
+    Quote,
+    Wildcard,
+    Other,
+}
+
+impl CharClass {
+    /// Returns the class of a character.
+    ///
+    /// * `token` - The character to classify.
+    pub fn classify(token: char) -> CharClass {
+        match token {
+            '\n' | '\r' => CharClass::LineEnd,
+            '{' => CharClass::Open,
+            '}' => CharClass::Close,
+            ';' => CharClass::Semicolon,
+            '#' => CharClass::Hash,
+            '"' => CharClass::Quote,
+            '*' => CharClass::Wildcard,
+            '.' => CharClass::Dot,
+            '-' | '_' => CharClass::NamePunct,
+            token if token.is_whitespace() => CharClass::Whitespace,
+            token if token.is_alphanumeric() => CharClass::AlphaNum,
+            _ => CharClass::Other,
+        }
+    }
+}
+
 /// The current parser state (really a tokenizer).
 ///
 /// * Starting - The initial state
@@ -31,6 +96,7 @@
 /// * Seeking - We're seeking the next directive.
 /// * Comment - We're reading comment text.
 /// * InDirective - We're reading a configuration directive.
+/// * InQuotedString - We're reading a quoted string inside a configuration directive.
 /// * Invalid - An illegal state transition.
 #[derive(PartialEq, Copy, Clone, Debug)]
 pub enum ParserState {
@@ -41,11 +107,25 @@ pub enum ParserState {
     Seeking,
     Comment,
     InDirective,
+    InQuotedString,
     Invalid,
 }
 
 impl ParserState {
-    /// Given a state and a token, returns the next state
+    /// Every parser state, in the order they are declared.
+    pub const ALL: [ParserState; 9] = [
+        ParserState::Starting,
+        ParserState::Name,
+        ParserState::StartBlock,
+        ParserState::EndBlock,
+        ParserState::Seeking,
+        ParserState::Comment,
+        ParserState::InDirective,
+        ParserState::InQuotedString,
+        ParserState::Invalid,
+    ];
+
+    /// Given a state and a token, returns the next state from the rows in `transitions`.
     ///
     /// ## State Transition Table
     ///
@@ -53,109 +133,121 @@ impl ParserState {
     /// |--------------|---------------|--------------|
     /// | Starting     | whitespace    | Starting     |
     /// | Starting     | alpha-numeric | Name         |
+    /// | Starting     | '*'           | Name         |
     /// | Starting     | '{'           | Start Block  |
     /// | Starting     | '#'           | Comment      |
     /// | Name         | alpha-numeric | Name         |
+    /// | Name         | '-' or '_'    | Name         |
     /// | Name         | whitespace    | Starting     |
     /// | Name         | '{'           | Start Block  |
     /// | Name         | '#'           | Comment      |
     /// | Start Block  | whitespace    | Seeking      |
-    /// | Start Block  | alpha-numeric | In Directive |
+    /// | Start Block  | name char     | In Directive |
     /// | Start Block  | '#'           | Comment      |
     /// | Start Bock   | '}'           | End Block    |
     /// | End Block    | whitespace    | End Block    |
     /// | End Block    | '#'           | Comment      |
     /// | Seeking      | whitespace    | Seeking      |
     /// | Seeking      | '}'           | End Block    |
+    /// | Seeking      | '{'           | Start Block  |
     /// | Seeking      | '#'           | Comment      |
-    /// | Seeking      | alpha-numeric | In Directive |
-    /// | Comment      | '\n'          | Comment      |
+    /// | Seeking      | name char     | In Directive |
+    /// | Comment      | '\n' or '\r'  | Seeking      |
     /// | Comment      | .             | Comment      |
     /// | In Directive | ';'           | Seeking      |
+    /// | In Directive | '{'           | Start Block  |
     /// | In Directive | '#'           | Comment      |
+    /// | In Directive | '"'           | In Quoted    |
     /// | In Directive | .             | In Directive |
+    /// | In Quoted    | '"'           | In Directive |
+    /// | In Quoted    | .             | In Quoted    |
+    ///
+    /// A name char is any character accepted by `is_directive_name_char`.
+    ///
+    /// Alpha-numeric means any Unicode letter or digit, both at the start of a container name
+    /// and after it.  jail(8) does not limit names to ASCII, so a name such as `élan` is read
+    /// as a name rather than rejected.  A '-' or '_' may follow the first character, as in
+    /// `web-01`, but a '.' may not.  A '*' may only start a name, as the name of the `*`
+    /// template block.
+    ///
+    /// A '{' while seeking or reading a directive opens a nested block, with the directive text
+    /// read so far becoming the name of the nested block.  Inside a quoted string, the
+    /// structural characters (';', '#', '{' and '}') are ordinary text.
     pub fn next_state(&self, token: char) -> ParserState {
+        let class = CharClass::classify(token);
+        self.transitions()
+            .iter()
+            .find(|(input, _)| *input == class)
+            .map_or_else(|| self.otherwise(), |(_, next)| *next)
+    }
+
+    /// The transitions out of the state, as the rows of the state transition table.  A
+    /// character whose class has no row leads to the state given by `otherwise`.
+    pub fn transitions(&self) -> &'static [(CharClass, ParserState)] {
         match self {
-            ParserState::Starting => {
-                if token.is_ascii_whitespace() {
-                    ParserState::Starting
-                } else if token.is_ascii_alphanumeric() {
-                    ParserState::Name
-                } else if token == '{' {
-                    ParserState::StartBlock
-                } else if token == '#' {
-                    ParserState::Comment
-                } else {
-                    ParserState::Invalid
-                }
-            },
-            ParserState::Name => {
-                if token.is_alphanumeric() {
-                    ParserState::Name
-                } else if token.is_ascii_whitespace() {
-                    ParserState::Starting
-                } else if token == '#' {
-                    ParserState::Comment
-                } else if token == '{' {
-                    ParserState::StartBlock
-                } else {
-                    ParserState::Invalid
-                }
-            }
-            ParserState::StartBlock => {
-                if token.is_ascii_whitespace() {
-                    ParserState::Seeking
-                } else if token.is_alphabetic() {
-                    ParserState::InDirective
-                } else if token == '#' {
-                    ParserState::Comment
-                } else if token == '}' {
-                    ParserState::EndBlock
-                } else {
-                    ParserState::Invalid
-                }
-            },
-            ParserState::EndBlock => {
-                if token.is_ascii_whitespace() {
-                    ParserState::EndBlock
-                } else if token == '#' {
-                    ParserState::Comment
-                } else {
-                    ParserState::Invalid
-                }
-            },
-            ParserState::Seeking => {
-                if token.is_ascii_whitespace() {
-                    ParserState::Seeking
-                } else if token == '#' {
-                    ParserState::Comment
-                } else if token.is_ascii_alphanumeric() {
-                    ParserState::InDirective
-                } else if token == '}' {
-                    ParserState::EndBlock
-                } else {
-                    ParserState::Invalid
-                }
-            },
-            ParserState::Comment => {
-                if token == '\n' {
-                    ParserState::Seeking
-                } else {
-                    ParserState::Comment
-                }
-            },
-            ParserState::InDirective => {
-                if token == ';' {
-                    ParserState::Seeking
-                } else if token == '#' {
-                    ParserState::Comment
-                } else {
-                    ParserState::InDirective
-                }
-            },
-            ParserState::Invalid => {
-                ParserState::Invalid
-            }
+            ParserState::Starting => &[
+                (CharClass::Whitespace, ParserState::Starting),
+                (CharClass::LineEnd, ParserState::Starting),
+                (CharClass::AlphaNum, ParserState::Name),
+                (CharClass::Wildcard, ParserState::Name),
+                (CharClass::Open, ParserState::StartBlock),
+                (CharClass::Hash, ParserState::Comment),
+            ],
+            ParserState::Name => &[
+                (CharClass::AlphaNum, ParserState::Name),
+                (CharClass::NamePunct, ParserState::Name),
+                (CharClass::Whitespace, ParserState::Starting),
+                (CharClass::LineEnd, ParserState::Starting),
+                (CharClass::Open, ParserState::StartBlock),
+                (CharClass::Hash, ParserState::Comment),
+            ],
+            ParserState::StartBlock => &[
+                (CharClass::Whitespace, ParserState::Seeking),
+                (CharClass::LineEnd, ParserState::Seeking),
+                (CharClass::AlphaNum, ParserState::InDirective),
+                (CharClass::Dot, ParserState::InDirective),
+                (CharClass::NamePunct, ParserState::InDirective),
+                (CharClass::Hash, ParserState::Comment),
+                (CharClass::Close, ParserState::EndBlock),
+            ],
+            ParserState::EndBlock => &[
+                (CharClass::Whitespace, ParserState::EndBlock),
+                (CharClass::LineEnd, ParserState::EndBlock),
+                (CharClass::Hash, ParserState::Comment),
+            ],
+            ParserState::Seeking => &[
+                (CharClass::Whitespace, ParserState::Seeking),
+                (CharClass::LineEnd, ParserState::Seeking),
+                (CharClass::Close, ParserState::EndBlock),
+                (CharClass::Open, ParserState::StartBlock),
+                (CharClass::Hash, ParserState::Comment),
+                (CharClass::AlphaNum, ParserState::InDirective),
+                (CharClass::Dot, ParserState::InDirective),
+                (CharClass::NamePunct, ParserState::InDirective),
+            ],
+            ParserState::Comment => &[
+                (CharClass::LineEnd, ParserState::Seeking),
+            ],
+            ParserState::InDirective => &[
+                (CharClass::Semicolon, ParserState::Seeking),
+                (CharClass::Open, ParserState::StartBlock),
+                (CharClass::Hash, ParserState::Comment),
+                (CharClass::Quote, ParserState::InQuotedString),
+            ],
+            ParserState::InQuotedString => &[
+                (CharClass::Quote, ParserState::InDirective),
+            ],
+            ParserState::Invalid => &[],
+        }
+    }
+
+    /// The next state for a character whose class has no row in `transitions`.  Comments,
+    /// directives and quoted strings take any other character as part of their text; in
+    /// every other state it is an illegal transition to `ParserState::Invalid`.
+    pub fn otherwise(&self) -> ParserState {
+        match self {
+            ParserState::Comment | ParserState::InDirective | ParserState::InQuotedString => *self,
+            _ => ParserState::Invalid,
         }
     }
 }
@@ -164,6 +256,100 @@ impl ParserState {
 mod test {
     use super::*;
 
+    /// The documented state transition table, with every state applied to a representative
+    /// of each kind of input.  Characters that the documented table has no row for lead to
+    /// `ParserState::Invalid`.
+    #[test]
+    fn test_transition_table() {
+        use ParserState::*;
+
+        let tokens = [' ', '\t', '\n', '\r', 'a', '4', '.', '_', '{', '}', '#', ';', '"', '=', '*'];
+        let expected = [
+            (Starting,       [Starting, Starting, Starting, Starting, Name, Name, Invalid, Invalid, StartBlock, Invalid, Comment, Invalid, Invalid, Invalid, Name]),
+            (Name,           [Starting, Starting, Starting, Starting, Name, Name, Invalid, Name, StartBlock, Invalid, Comment, Invalid, Invalid, Invalid, Invalid]),
+            (StartBlock,     [Seeking, Seeking, Seeking, Seeking, InDirective, InDirective, InDirective, InDirective, Invalid, EndBlock, Comment, Invalid, Invalid, Invalid, Invalid]),
+            (EndBlock,       [EndBlock, EndBlock, EndBlock, EndBlock, Invalid, Invalid, Invalid, Invalid, Invalid, Invalid, Comment, Invalid, Invalid, Invalid, Invalid]),
+            (Seeking,        [Seeking, Seeking, Seeking, Seeking, InDirective, InDirective, InDirective, InDirective, StartBlock, EndBlock, Comment, Invalid, Invalid, Invalid, Invalid]),
+            (Comment,        [Comment, Comment, Seeking, Seeking, Comment, Comment, Comment, Comment, Comment, Comment, Comment, Comment, Comment, Comment, Comment]),
+            (InDirective,    [InDirective, InDirective, InDirective, InDirective, InDirective, InDirective, InDirective, InDirective, StartBlock, InDirective, Comment, Seeking, InQuotedString, InDirective, InDirective]),
+            (InQuotedString, [InQuotedString, InQuotedString, InQuotedString, InQuotedString, InQuotedString, InQuotedString, InQuotedString, InQuotedString, InQuotedString, InQuotedString, InQuotedString, InQuotedString, InDirective, InQuotedString, InQuotedString]),
+            (Invalid,        [Invalid; 15]),
+        ];
+
+        assert_eq!(expected.map(|(state, _)| state), ParserState::ALL);
+        for (state, next_states) in expected {
+            for (token, next_state) in tokens.iter().zip(next_states) {
+                assert_eq!(state.next_state(*token), next_state, "{:?} on {:?}", state, token);
+            }
+        }
+    }
+
+    #[test]
+    fn test_transitions() {
+        for state in ParserState::ALL {
+            for (class, next_state) in state.transitions() {
+                assert_ne!(*next_state, ParserState::Invalid, "{:?} on {:?}", state, class);
+                assert_eq!(state.transitions().iter().filter(|(other, _)| other == class).count(), 1);
+            }
+        }
+        assert!(ParserState::Invalid.transitions().is_empty());
+        assert_eq!(ParserState::Comment.otherwise(), ParserState::Comment);
+        assert_eq!(ParserState::Seeking.otherwise(), ParserState::Invalid);
+    }
+
+    #[test]
+    fn test_classify() {
+        let expected = [
+            (' ', CharClass::Whitespace),
+            ('\t', CharClass::Whitespace),
+            ('\u{0B}', CharClass::Whitespace),
+            ('\u{A0}', CharClass::Whitespace),
+            ('\u{2003}', CharClass::Whitespace),
+            ('\n', CharClass::LineEnd),
+            ('\r', CharClass::LineEnd),
+            ('a', CharClass::AlphaNum),
+            ('Z', CharClass::AlphaNum),
+            ('4', CharClass::AlphaNum),
+            ('é', CharClass::AlphaNum),
+            ('.', CharClass::Dot),
+            ('-', CharClass::NamePunct),
+            ('_', CharClass::NamePunct),
+            ('{', CharClass::Open),
+            ('}', CharClass::Close),
+            (';', CharClass::Semicolon),
+            ('#', CharClass::Hash),
+            ('"', CharClass::Quote),
+            ('*', CharClass::Wildcard),
+            ('=', CharClass::Other),
+            ('$', CharClass::Other),
+            ('€', CharClass::Other),
+        ];
+
+        for (token, class) in expected {
+            assert_eq!(CharClass::classify(token), class, "{:?}", token);
+        }
+    }
+
+    #[test]
+    fn test_directive_name_char() {
+        for token in ['a', 'Z', '4', '.', '-', '_'] {
+            assert!(is_directive_name_char(token));
+        }
+
+        for token in [' ', '=', '"', ';', '{', '}', '#', '+'] {
+            assert!(!is_directive_name_char(token));
+        }
+    }
+
+    #[test]
+    fn test_unicode_name() {
+        // Non-ASCII letters are allowed anywhere in a container name, as jail(8) allows them.
+        assert_eq!(ParserState::Starting.next_state('é'), ParserState::Name);
+        assert_eq!(ParserState::Name.next_state('é'), ParserState::Name);
+        assert_eq!(ParserState::Starting.next_state('7'), ParserState::Name);
+        assert_eq!(ParserState::Starting.next_state('€'), ParserState::Invalid);
+    }
+
     #[test]
     fn test_start_state() {
         let current_state = ParserState::Starting;
@@ -214,6 +400,10 @@ mod test {
         let next_state = current_state.next_state('a');
         assert_eq!(next_state, ParserState::InDirective);
 
+        for token in ['4', '_', '.'] {
+            assert_eq!(current_state.next_state(token), ParserState::Seeking.next_state(token));
+        }
+
         let next_state = current_state.next_state('}');
         assert_eq!(next_state, ParserState::EndBlock);
     }
@@ -246,10 +436,16 @@ mod test {
         let next_state = current_state.next_state('a');
         assert_eq!(next_state, ParserState::InDirective);
 
+        let next_state = current_state.next_state('.');
+        assert_eq!(next_state, ParserState::InDirective);
+
         let next_state = current_state.next_state('}');
         assert_eq!(next_state, ParserState::EndBlock);
 
         let next_state = current_state.next_state('{');
+        assert_eq!(next_state, ParserState::StartBlock);
+
+        let next_state = current_state.next_state('@');
         assert_eq!(next_state, ParserState::Invalid);
     }
 
@@ -270,6 +466,9 @@ mod test {
 
         let next_state = current_state.next_state('\n');
         assert_eq!(next_state, ParserState::Seeking);
+
+        let next_state = current_state.next_state('\r');
+        assert_eq!(next_state, ParserState::Seeking);
     }
 
     #[test]
@@ -282,7 +481,7 @@ mod test {
         assert_eq!(next_state, ParserState::Comment);
 
         let next_state = current_state.next_state('{');
-        assert_eq!(next_state, ParserState::InDirective);
+        assert_eq!(next_state, ParserState::StartBlock);
 
         let next_state = current_state.next_state('a');
         assert_eq!(next_state, ParserState::InDirective);
@@ -294,6 +493,22 @@ mod test {
         assert_eq!(next_state, ParserState::Seeking);
     }
 
+    #[test]
+    fn test_in_quoted_string() {
+        let current_state = ParserState::InDirective;
+        let next_state = current_state.next_state('"');
+        assert_eq!(next_state, ParserState::InQuotedString);
+
+        let current_state = ParserState::InQuotedString;
+        for token in [' ', ';', '#', '{', '}', 'a', '\n'] {
+            let next_state = current_state.next_state(token);
+            assert_eq!(next_state, ParserState::InQuotedString);
+        }
+
+        let next_state = current_state.next_state('"');
+        assert_eq!(next_state, ParserState::InDirective);
+    }
+
     #[test]
     fn test_invalid() {
         let current_state = ParserState::Invalid;
@@ -0,0 +1,38 @@
+//!
+//! Copyright (c) 2026, Paul C. Hoehne
+//!
+//! Redistribution and use in source and binary forms, with or without modification, are 
+//! permitted provided that the following conditions are met:
+//!
+//!   Redistributions of source code must retain the above copyright notice, this list of 
+//!   conditions and the following disclaimer.
+//! 
+//!   Redistributions in binary form must reproduce the above copyright notice, this list of 
+//!   conditions and the following disclaimer in the documentation and/or other materials 
+//!   provided with the distribution.
+//!
+//! THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY 
+//! EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF 
+//! MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL 
+//! THE COPYRIGHT OWNER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, 
+//! SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT 
+//! OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) 
+//! HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, 
+//! OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS 
+//! SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+//! 
+
+use conmand::{Configuration, ParseError, parse_jail_conf};
+
+#[test]
+fn test_parse_jail_conf() -> Result<(), ParseError> {
+    let configurations = parse_jail_conf("examples/fixtures/jails.conf")?;
+
+    let names: Vec<&str> = configurations.iter().map(|configuration| configuration.name.as_str()).collect();
+    assert_eq!(names, vec!["frodo", "sam", "pippin"]);
+    let counts: Vec<usize> = configurations.iter().map(Configuration::len).collect();
+    assert_eq!(counts, vec![5, 3, 2]);
+    assert_eq!(configurations[1].get_string("path"), Some("/usr/local/jails/containers/sam"));
+
+    Ok(())
+}